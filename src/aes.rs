@@ -0,0 +1,311 @@
+// Pure Rust AES (FIPS-197), usable anywhere a crypter::BlockCipher is expected.
+// The state is kept in the FIPS layout: byte i sits in column i / 4, row i % 4.
+// source: https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197.pdf
+
+use crypter::BlockCipher;
+use error::{Error, Result};
+
+pub const BLOCK_SIZE: usize = 16;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const INV_SBOX: [u8; 256] = [
+    0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb,
+    0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb,
+    0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e,
+    0x08, 0x2e, 0xa1, 0x66, 0x28, 0xd9, 0x24, 0xb2, 0x76, 0x5b, 0xa2, 0x49, 0x6d, 0x8b, 0xd1, 0x25,
+    0x72, 0xf8, 0xf6, 0x64, 0x86, 0x68, 0x98, 0x16, 0xd4, 0xa4, 0x5c, 0xcc, 0x5d, 0x65, 0xb6, 0x92,
+    0x6c, 0x70, 0x48, 0x50, 0xfd, 0xed, 0xb9, 0xda, 0x5e, 0x15, 0x46, 0x57, 0xa7, 0x8d, 0x9d, 0x84,
+    0x90, 0xd8, 0xab, 0x00, 0x8c, 0xbc, 0xd3, 0x0a, 0xf7, 0xe4, 0x58, 0x05, 0xb8, 0xb3, 0x45, 0x06,
+    0xd0, 0x2c, 0x1e, 0x8f, 0xca, 0x3f, 0x0f, 0x02, 0xc1, 0xaf, 0xbd, 0x03, 0x01, 0x13, 0x8a, 0x6b,
+    0x3a, 0x91, 0x11, 0x41, 0x4f, 0x67, 0xdc, 0xea, 0x97, 0xf2, 0xcf, 0xce, 0xf0, 0xb4, 0xe6, 0x73,
+    0x96, 0xac, 0x74, 0x22, 0xe7, 0xad, 0x35, 0x85, 0xe2, 0xf9, 0x37, 0xe8, 0x1c, 0x75, 0xdf, 0x6e,
+    0x47, 0xf1, 0x1a, 0x71, 0x1d, 0x29, 0xc5, 0x89, 0x6f, 0xb7, 0x62, 0x0e, 0xaa, 0x18, 0xbe, 0x1b,
+    0xfc, 0x56, 0x3e, 0x4b, 0xc6, 0xd2, 0x79, 0x20, 0x9a, 0xdb, 0xc0, 0xfe, 0x78, 0xcd, 0x5a, 0xf4,
+    0x1f, 0xdd, 0xa8, 0x33, 0x88, 0x07, 0xc7, 0x31, 0xb1, 0x12, 0x10, 0x59, 0x27, 0x80, 0xec, 0x5f,
+    0x60, 0x51, 0x7f, 0xa9, 0x19, 0xb5, 0x4a, 0x0d, 0x2d, 0xe5, 0x7a, 0x9f, 0x93, 0xc9, 0x9c, 0xef,
+    0xa0, 0xe0, 0x3b, 0x4d, 0xae, 0x2a, 0xf5, 0xb0, 0xc8, 0xeb, 0xbb, 0x3c, 0x83, 0x53, 0x99, 0x61,
+    0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d,
+];
+
+// multiply by x in GF(2^8)
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+pub fn sub_bytes(state: &mut [u8; 16]) {
+    for b in state.iter_mut() {
+        *b = SBOX[*b as usize];
+    }
+}
+
+pub fn inv_sub_bytes(state: &mut [u8; 16]) {
+    for b in state.iter_mut() {
+        *b = INV_SBOX[*b as usize];
+    }
+}
+
+// row r is rotated left by r columns
+pub fn shift_rows(state: &mut [u8; 16]) {
+    let s = *state;
+    for c in 0..4 {
+        for r in 0..4 {
+            state[c * 4 + r] = s[((c + r) % 4) * 4 + r];
+        }
+    }
+}
+
+pub fn inv_shift_rows(state: &mut [u8; 16]) {
+    let s = *state;
+    for c in 0..4 {
+        for r in 0..4 {
+            state[((c + r) % 4) * 4 + r] = s[c * 4 + r];
+        }
+    }
+}
+
+pub fn mix_columns(state: &mut [u8; 16]) {
+    for col in state.chunks_mut(4) {
+        let (a0, a1, a2, a3) = (col[0], col[1], col[2], col[3]);
+        col[0] = xtime(a0) ^ xtime(a1) ^ a1 ^ a2 ^ a3;
+        col[1] = a0 ^ xtime(a1) ^ xtime(a2) ^ a2 ^ a3;
+        col[2] = a0 ^ a1 ^ xtime(a2) ^ xtime(a3) ^ a3;
+        col[3] = xtime(a0) ^ a0 ^ a1 ^ a2 ^ xtime(a3);
+    }
+}
+
+// InvMixColumns is MixColumns preceded by multiplying with {04}x^2 + {05}, see
+// "The Design of Rijndael" section 4.1.3
+pub fn inv_mix_columns(state: &mut [u8; 16]) {
+    for col in state.chunks_mut(4) {
+        let u = xtime(xtime(col[0] ^ col[2]));
+        let v = xtime(xtime(col[1] ^ col[3]));
+        col[0] ^= u;
+        col[1] ^= v;
+        col[2] ^= u;
+        col[3] ^= v;
+    }
+    mix_columns(state);
+}
+
+pub fn add_round_key(state: &mut [u8; 16], round_key: &[u8; 16]) {
+    for (s, k) in state.iter_mut().zip(round_key.iter()) {
+        *s ^= k;
+    }
+}
+
+/// Expands a 16, 24 or 32 byte key into `rounds + 1` round keys
+pub fn expand_key(key: &[u8], rounds: usize) -> Result<Vec<[u8; 16]>> {
+    if key.len() != 16 && key.len() != 24 && key.len() != 32 {
+        return Err(Error::InvalidKeyLength(key.len()));
+    }
+    let nk = key.len() / 4;
+    let total_words = 4 * (rounds + 1);
+
+    let mut words: Vec<[u8; 4]> = key.chunks(4).map(|w| [w[0], w[1], w[2], w[3]]).collect();
+    // the round constants are the powers of x, doubled as they go so extended key schedules
+    // keep going past the ten the standard ones need
+    let mut rcon = 0x01;
+    for i in nk..total_words {
+        let mut temp = words[i - 1];
+        if i % nk == 0 {
+            // RotWord, SubWord then Rcon
+            temp = [SBOX[temp[1] as usize], SBOX[temp[2] as usize], SBOX[temp[3] as usize], SBOX[temp[0] as usize]];
+            temp[0] ^= rcon;
+            rcon = xtime(rcon);
+        } else if nk > 6 && i % nk == 4 {
+            for b in temp.iter_mut() {
+                *b = SBOX[*b as usize];
+            }
+        }
+        let prev = words[i - nk];
+        words.push([prev[0] ^ temp[0], prev[1] ^ temp[1], prev[2] ^ temp[2], prev[3] ^ temp[3]]);
+    }
+
+    words.truncate(total_words);
+    Ok(words.chunks(4).map(|w| {
+        let mut round_key = [0u8; 16];
+        for (idx, word) in w.iter().enumerate() {
+            round_key[idx * 4..idx * 4 + 4].copy_from_slice(word);
+        }
+        round_key
+    }).collect())
+}
+
+pub struct Aes {
+    round_keys: Vec<[u8; 16]>,
+}
+
+impl Aes {
+    /// AES-128, AES-192 or AES-256 depending on the key length
+    pub fn new(key: &[u8]) -> Result<Aes> {
+        Self::with_rounds(key, key.len() / 4 + 6)
+    }
+
+    /// Reduced (or extended) round variant, the last round still skips MixColumns
+    pub fn with_rounds(key: &[u8], rounds: usize) -> Result<Aes> {
+        if rounds == 0 {
+            return Err(Error::InvalidRounds(rounds));
+        }
+        Ok(Aes { round_keys: expand_key(key, rounds)? })
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.round_keys.len() - 1
+    }
+
+    pub fn round_keys(&self) -> &[[u8; 16]] {
+        &self.round_keys
+    }
+
+    pub fn encrypt(&self, state: &mut [u8; 16]) {
+        let rounds = self.rounds();
+        add_round_key(state, &self.round_keys[0]);
+        for round_key in &self.round_keys[1..rounds] {
+            sub_bytes(state);
+            shift_rows(state);
+            mix_columns(state);
+            add_round_key(state, round_key);
+        }
+        sub_bytes(state);
+        shift_rows(state);
+        add_round_key(state, &self.round_keys[rounds]);
+    }
+
+    pub fn decrypt(&self, state: &mut [u8; 16]) {
+        let rounds = self.rounds();
+        add_round_key(state, &self.round_keys[rounds]);
+        for round_key in self.round_keys[1..rounds].iter().rev() {
+            inv_shift_rows(state);
+            inv_sub_bytes(state);
+            add_round_key(state, round_key);
+            inv_mix_columns(state);
+        }
+        inv_shift_rows(state);
+        inv_sub_bytes(state);
+        add_round_key(state, &self.round_keys[0]);
+    }
+}
+
+impl BlockCipher for Aes {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) {
+        let mut state = [0u8; 16];
+        state.copy_from_slice(block);
+        self.encrypt(&mut state);
+        block.copy_from_slice(&state);
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) {
+        let mut state = [0u8; 16];
+        state.copy_from_slice(block);
+        self.decrypt(&mut state);
+        block.copy_from_slice(&state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conversions::{string_to_hex, hex_to_string};
+    use crypter::{OpensslCipher, CipherMode, crypt, random_bytes};
+    use openssl::symm::Mode;
+
+    fn fips_vector(key: &str, expected: &str) {
+        let aes = Aes::new(&string_to_hex(key).unwrap()).unwrap();
+        let mut block = string_to_hex("00112233445566778899aabbccddeeff").unwrap();
+        aes.encrypt_block(&mut block);
+        assert_eq!(hex_to_string(&block), expected);
+
+        aes.decrypt_block(&mut block);
+        assert_eq!(hex_to_string(&block), "00112233445566778899aabbccddeeff");
+    }
+
+    #[test]
+    fn fips_197_appendix_c() {
+        fips_vector("000102030405060708090a0b0c0d0e0f", "69c4e0d86a7b0430d8cdb78070b4c55a");
+        fips_vector("000102030405060708090a0b0c0d0e0f1011121314151617", "dda97ca4864cdfe06eaf70a0ec0d7191");
+        fips_vector("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "8ea2b7ca516745bfeafc49904b496089");
+    }
+
+    #[test]
+    fn fips_197_key_expansion() {
+        // appendix A.1, last round key is w[40..43]
+        let aes = Aes::new(&string_to_hex("2b7e151628aed2a6abf7158809cf4f3c").unwrap()).unwrap();
+        assert_eq!(aes.rounds(), 10);
+        assert_eq!(hex_to_string(&aes.round_keys()[10]), "d014f9a8c9ee2589e13f0cc8b6630ca6");
+    }
+
+    #[test]
+    fn matches_openssl() {
+        for &key_size in [16, 24, 32].iter() {
+            let key = random_bytes(key_size, key_size);
+            let iv = random_bytes(16, 16);
+            let plain = random_bytes(160, 160);
+
+            let (native, openssl) = (Aes::new(&key).unwrap(), OpensslCipher::aes(&key).unwrap());
+            for &cipher_mode in [CipherMode::Ecb, CipherMode::Cbc, CipherMode::Ctr].iter() {
+                let iv = if cipher_mode == CipherMode::Ctr { &iv[..8] } else { &iv[..] };
+                let encrypted = crypt(&native, cipher_mode, Some(iv), &plain, Mode::Encrypt).unwrap();
+                assert_eq!(encrypted, crypt(&openssl, cipher_mode, Some(iv), &plain, Mode::Encrypt).unwrap());
+                assert_eq!(crypt(&native, cipher_mode, Some(iv), &encrypted, Mode::Decrypt).unwrap(), plain);
+            }
+        }
+    }
+
+    #[test]
+    fn reduced_rounds() {
+        let aes = Aes::with_rounds(b"YELLOW SUBMARINE", 4).unwrap();
+        assert_eq!(aes.rounds(), 4);
+        assert_eq!(aes.round_keys()[..5], Aes::new(b"YELLOW SUBMARINE").unwrap().round_keys()[..5]);
+
+        let mut block = *b"ICE ICE BABY\x04\x04\x04\x04";
+        aes.encrypt(&mut block);
+        aes.decrypt(&mut block);
+        assert_eq!(&block, b"ICE ICE BABY\x04\x04\x04\x04");
+
+        match Aes::with_rounds(b"YELLOW SUBMARINE", 0) {
+            Err(Error::InvalidRounds(0)) => (),
+            other => panic!("expected InvalidRounds, got {:?}", other.map(|aes| aes.rounds())),
+        }
+    }
+
+    #[test]
+    fn extended_rounds() {
+        let aes = Aes::with_rounds(b"YELLOW SUBMARINE", 14).unwrap();
+        assert_eq!(aes.round_keys()[..11], Aes::new(b"YELLOW SUBMARINE").unwrap().round_keys()[..]);
+
+        // the first byte of each AES-128 round key takes in the round constant, which keeps
+        // doubling after 0x36
+        let rcon = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36, 0x6c, 0xd8, 0xab, 0x4d];
+        for (round, &expected) in rcon.iter().enumerate() {
+            let (previous, key) = (aes.round_keys()[round], aes.round_keys()[round + 1]);
+            assert_eq!(key[0] ^ previous[0] ^ SBOX[previous[13] as usize], expected);
+        }
+
+        let mut block = *b"ICE ICE BABY\x04\x04\x04\x04";
+        aes.encrypt(&mut block);
+        aes.decrypt(&mut block);
+        assert_eq!(&block, b"ICE ICE BABY\x04\x04\x04\x04");
+    }
+}
@@ -0,0 +1,138 @@
+// Interactive crib dragging over cipher texts that share a key stream.
+//
+//     cribdrag [--base64] FILE
+//
+// FILE holds one cipher text per line, hex unless --base64 is given. Commands are read from
+// stdin, `help` lists them.
+
+extern crate matasano;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use matasano::conversions::{string_to_hex_lenient, Base64};
+use matasano::crib::CribDragger;
+
+
+const HELP: &str = "\
+show                      every cipher text as far as it is known
+drag IDX CRIB             best offsets for CRIB in cipher text IDX
+lock IDX OFFSET TEXT      take TEXT as the plain text of IDX at OFFSET
+unlock OFFSET LEN         forget key stream bytes
+key                       the key stream, ?? where unknown
+quit";
+
+// how many placements drag lists
+const SHOWN: usize = 10;
+
+fn printable(text: &[u8]) -> String {
+    text.iter().map(|&b| if (0x20..0x7f).contains(&b) { b as char } else { '.' }).collect()
+}
+
+fn show(dragger: &CribDragger) {
+    for idx in 0..dragger.cipher_texts().len() {
+        if let Ok(text) = dragger.render(idx, '_') {
+            println!("{:3}  {}", idx, text);
+        }
+    }
+}
+
+fn run(dragger: &mut CribDragger, line: &str) -> Result<bool, String> {
+    let mut words = line.splitn(2, ' ');
+    let command = words.next().unwrap_or("");
+    let rest = words.next().unwrap_or("");
+    let number = |s: Option<&str>| -> Result<usize, String> {
+        s.ok_or("missing argument")?.parse().map_err(|e| format!("{}", e))
+    };
+    let text_index = |idx: usize| -> Result<usize, String> {
+        match idx < dragger.cipher_texts().len() {
+            true => Ok(idx),
+            false => Err(format!("there is no cipher text {}", idx)),
+        }
+    };
+
+    match command {
+        "" => (),
+        "show" => show(dragger),
+        "drag" => {
+            let mut args = rest.splitn(2, ' ');
+            let idx = text_index(number(args.next())?)?;
+            let crib = args.next().ok_or("missing crib")?;
+            let placements = dragger.drag(idx, crib.as_bytes()).map_err(|e| format!("{}", e))?;
+            for placement in placements.iter().take(SHOWN) {
+                let others: Vec<String> = placement.others.iter().map(|o| printable(o)).collect();
+                println!("{:4} {:6.3}  {}", placement.offset, placement.score, others.join(" | "));
+            }
+        }
+        "lock" => {
+            let mut args = rest.splitn(3, ' ');
+            let idx = text_index(number(args.next())?)?;
+            let offset = number(args.next())?;
+            let text = args.next().ok_or("missing text")?;
+            dragger.lock(idx, offset, text.as_bytes()).map_err(|e| format!("{}", e))?;
+            show(dragger);
+        }
+        "unlock" => {
+            let mut args = rest.split_whitespace();
+            let (offset, len) = (number(args.next())?, number(args.next())?);
+            dragger.unlock(offset, len);
+            show(dragger);
+        }
+        "key" => {
+            let key: Vec<String> = dragger.key_stream().iter()
+                .map(|k| k.map(|k| format!("{:02x}", k)).unwrap_or_else(|| "??".to_string()))
+                .collect();
+            println!("{}", key.concat());
+        }
+        "help" => println!("{}", HELP),
+        "quit" | "exit" => return Ok(false),
+        other => return Err(format!("unknown command {:?}, try help", other)),
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let base64 = args.iter().any(|a| a == "--base64");
+    let path = match args.iter().find(|a| !a.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: cribdrag [--base64] FILE");
+            process::exit(2);
+        }
+    };
+
+    let contents = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let cipher_texts: Result<Vec<Vec<u8>>, _> = contents.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| if base64 { Base64::STANDARD.decode(l.trim()) } else { string_to_hex_lenient(l) })
+        .collect();
+    let mut dragger = match cipher_texts {
+        Ok(cipher_texts) => CribDragger::new(cipher_texts),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    show(&dragger);
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        match run(&mut dragger, line.trim_end_matches(['\r', '\n'])) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
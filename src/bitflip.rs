@@ -0,0 +1,262 @@
+// Bitflipping, rewriting plain text through unauthenticated cipher text. Under CTR a flipped
+// cipher text bit flips the same plain text bit. Under CBC it flips that bit in the next
+// block and scrambles the block that was flipped.
+
+use crypter::CipherMode;
+use error::{Error, Result};
+
+
+// what has to be xored in to turn known into wanted
+fn mask(known: &[u8], wanted: &[u8]) -> Result<Vec<u8>> {
+    if known.len() != wanted.len() {
+        return Err(Error::LengthMismatch(known.len(), wanted.len()));
+    }
+    Ok(known.iter().zip(wanted.iter()).map(|(k, w)| k ^ w).collect())
+}
+
+/// Rewrites known plain text at offset to wanted in CTR (or any other stream) cipher text
+pub fn ctr_flip(cipher_text: &[u8], offset: usize, known: &[u8], wanted: &[u8]) -> Result<Vec<u8>> {
+    if offset + known.len() > cipher_text.len() {
+        return Err(Error::Unflippable(offset));
+    }
+
+    let mask = mask(known, wanted)?;
+    let mut flipped = cipher_text.to_vec();
+    for (c, m) in flipped[offset..].iter_mut().zip(mask) {
+        *c ^= m;
+    }
+    Ok(flipped)
+}
+
+/// Rewrites known plain text at offset to wanted in CBC cipher text by flipping the block in
+/// front, which decrypts to garbage afterwards. The rewrite has to stay inside one block
+/// and can't be in the first block unless the IV leads the cipher text.
+pub fn cbc_flip(cipher_text: &[u8], block_size: usize, offset: usize, known: &[u8], wanted: &[u8]) -> Result<Vec<u8>> {
+    if block_size == 0 {
+        return Err(Error::InvalidBlockSize(block_size));
+    }
+    let in_one_block = known.is_empty() || offset / block_size == (offset + known.len() - 1) / block_size;
+    if offset < block_size || !in_one_block || offset + known.len() > cipher_text.len() {
+        return Err(Error::Unflippable(offset));
+    }
+
+    let mask = mask(known, wanted)?;
+    let mut flipped = cipher_text.to_vec();
+    for (c, m) in flipped[offset - block_size..].iter_mut().zip(mask) {
+        *c ^= m;
+    }
+    Ok(flipped)
+}
+
+/// Injects bytes the target filters out of its input into a record that encrypts
+/// `prefix + input + suffix`. A stand-in with the filtered bytes flipped goes in as input
+/// and gets flipped back in the cipher text.
+pub struct Bitflip {
+    cipher_mode: CipherMode,
+    block_size: usize,
+    prefix_len: usize,
+    forbidden: Vec<u8>,
+    tolerate_scrambling: bool,
+}
+
+impl Bitflip {
+    /// By default a block of filler in the input takes the scrambling, so nothing the target
+    /// wrote gets damaged
+    pub fn cbc(block_size: usize, prefix_len: usize) -> Bitflip {
+        Bitflip { cipher_mode: CipherMode::Cbc, block_size, prefix_len, forbidden: vec!(), tolerate_scrambling: false }
+    }
+
+    pub fn ctr(prefix_len: usize) -> Bitflip {
+        Bitflip { cipher_mode: CipherMode::Ctr, block_size: 1, prefix_len, forbidden: vec!(), tolerate_scrambling: false }
+    }
+
+    /// Bytes the target strips or escapes from the input
+    pub fn forbidding(mut self, bytes: &[u8]) -> Self {
+        self.forbidden.extend_from_slice(bytes);
+        self
+    }
+
+    /// Lets CBC scramble the block in front of the stand-in wherever it is, even when that's
+    /// part of the prefix. Takes less input, for targets that don't mind a garbled field.
+    pub fn tolerate_scrambling(mut self) -> Self {
+        self.tolerate_scrambling = true;
+        self
+    }
+
+    fn allowed(&self, byte: u8) -> bool {
+        !self.forbidden.contains(&byte)
+    }
+
+    // the least flipping that gets each byte past the filter
+    fn stand_in(&self, wanted: &[u8]) -> Result<Vec<u8>> {
+        wanted.iter()
+            .map(|&w| (0..=255).map(|bit| w ^ bit).find(|&b| self.allowed(b)).ok_or(Error::EveryByteForbidden))
+            .collect()
+    }
+
+    /// The input to send and the offset of the stand-in for wanted in the record. Under CBC
+    /// wanted can be at most a block long.
+    pub fn input_for(&self, wanted: &[u8]) -> Result<(Vec<u8>, usize)> {
+        let filler = (b'A'..=b'Z').chain(0..=255).find(|&b| self.allowed(b)).ok_or(Error::EveryByteForbidden)?;
+        let block_size = self.block_size;
+
+        let mut offset = self.prefix_len;
+        if self.cipher_mode == CipherMode::Cbc {
+            if block_size == 0 {
+                return Err(Error::InvalidBlockSize(block_size));
+            }
+            // CBC rewrites at most a block at a time
+            if wanted.len() > block_size {
+                return Err(Error::Unflippable(self.prefix_len));
+            }
+            if !self.tolerate_scrambling {
+                // a whole block of filler in front
+                offset = offset.div_ceil(block_size) * block_size + block_size;
+            } else if offset < block_size {
+                // the first block only flips through the IV
+                offset = block_size;
+            } else if offset % block_size + wanted.len() > block_size {
+                offset = offset.div_ceil(block_size) * block_size;
+            }
+        }
+
+        let mut input = vec![filler; offset - self.prefix_len];
+        input.extend(self.stand_in(wanted)?);
+        Ok((input, offset))
+    }
+
+    /// Rewrites the stand-in sent by `input_for` in the cipher text the target returned
+    pub fn flip(&self, cipher_text: &[u8], wanted: &[u8]) -> Result<Vec<u8>> {
+        let (_, offset) = self.input_for(wanted)?;
+        let known = self.stand_in(wanted)?;
+        match self.cipher_mode {
+            CipherMode::Cbc => cbc_flip(cipher_text, self.block_size, offset, &known, wanted),
+            _ => ctr_flip(cipher_text, offset, &known, wanted),
+        }
+    }
+
+    /// Encrypts the stand-in through the oracle and flips it into wanted
+    pub fn inject<O: Fn(&[u8]) -> Vec<u8> + ?Sized>(&self, oracle: &O, wanted: &[u8]) -> Result<Vec<u8>> {
+        let (input, _) = self.input_for(wanted)?;
+        self.flip(&oracle(&input), wanted)
+    }
+
+    /// Checks a target against the attack with its own acceptance check, like a parser looking
+    /// for `;admin=true;`. True when the untouched record is rejected and the flipped one is
+    /// accepted, so an authenticated format should come out false.
+    pub fn verify<O, F>(&self, oracle: &O, wanted: &[u8], accepts: F) -> Result<bool>
+            where O: Fn(&[u8]) -> Vec<u8> + ?Sized, F: Fn(&[u8]) -> bool {
+        let (input, _) = self.input_for(wanted)?;
+        let cipher_text = oracle(&input);
+        if accepts(&cipher_text) {
+            return Ok(false);
+        }
+        Ok(accepts(&self.flip(&cipher_text, wanted)?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use conversions::{pad_pkcs7, Padding, Pkcs7};
+    use crypter::{cbc, ctr, random_aes_key};
+    use openssl::symm::Mode;
+
+    const PREFIX: &[u8] = b"comment1=cooking%20MCs;userdata=";
+    const SUFFIX: &[u8] = b";comment2=%20like%20a%20pound%20of%20bacon";
+
+    fn record(input: &[u8]) -> Vec<u8> {
+        let quoted: Vec<u8> = input.iter().cloned().filter(|&b| b != b';' && b != b'=').collect();
+        [PREFIX, &quoted, SUFFIX].concat()
+    }
+
+    fn is_admin(plain_text: &[u8]) -> bool {
+        plain_text.windows(12).any(|w| w == b";admin=true;")
+    }
+
+    #[test]
+    fn cbc_keeps_the_prefix() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = |input: &[u8]| {
+            let mut plain_text = record(input);
+            pad_pkcs7(&mut plain_text, 16).unwrap();
+            cbc(&cipher, &[0; 16], &plain_text, Mode::Encrypt).unwrap()
+        };
+        let decrypt = |cipher_text: &[u8]| {
+            let mut plain_text = cbc(&cipher, &[0; 16], cipher_text, Mode::Decrypt).unwrap();
+            Pkcs7.unpad(&mut plain_text, 16).map(|_| plain_text).unwrap_or_default()
+        };
+
+        let flipper = Bitflip::cbc(16, PREFIX.len()).forbidding(b";=");
+        let (input, offset) = flipper.input_for(b";admin=true;").unwrap();
+        assert_eq!((input.len(), offset), (28, 48));
+
+        let plain_text = decrypt(&flipper.inject(&oracle, b";admin=true;").unwrap());
+        assert_eq!(&plain_text[..PREFIX.len()], PREFIX);
+        assert!(is_admin(&plain_text));
+        assert!(flipper.verify(&oracle, b";admin=true;", |c| is_admin(&decrypt(c))).unwrap());
+
+        // the block in front of the stand-in is the end of the prefix now
+        let tolerant = Bitflip::cbc(16, PREFIX.len()).forbidding(b";=").tolerate_scrambling();
+        assert_eq!(tolerant.input_for(b";admin=true;").unwrap().1, 32);
+        let plain_text = decrypt(&tolerant.inject(&oracle, b";admin=true;").unwrap());
+        assert!(is_admin(&plain_text) && plain_text[16..32] != PREFIX[16..32]);
+    }
+
+    #[test]
+    fn ctr_in_place() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = |input: &[u8]| ctr(&cipher, 0, &record(input)).unwrap();
+        let flipper = Bitflip::ctr(PREFIX.len()).forbidding(b";=");
+
+        let plain_text = ctr(&cipher, 0, &flipper.inject(&oracle, b";admin=true;").unwrap()).unwrap();
+        assert_eq!(plain_text, [PREFIX, b";admin=true;", SUFFIX].concat());
+        assert!(flipper.verify(&oracle, b";admin=true;", |c| is_admin(&ctr(&cipher, 0, c).unwrap())).unwrap());
+
+        // a format that refuses anything it didn't write itself
+        let issued = oracle(&flipper.input_for(b";admin=true;").unwrap().0);
+        assert!(!flipper.verify(&oracle, b";admin=true;", |c| c == &issued[..] && is_admin(&ctr(&cipher, 0, c).unwrap())).unwrap());
+    }
+
+    #[test]
+    fn unflippable() {
+        let cipher_text = [0; 48];
+        for &(offset, len) in &[(4, 4), (30, 4), (46, 4)] {
+            match cbc_flip(&cipher_text, 16, offset, &vec![0; len], &vec![1; len]) {
+                Err(Error::Unflippable(at)) => assert_eq!(at, offset),
+                other => panic!("expected Unflippable, got {:?}", other),
+            }
+        }
+        assert_eq!(cbc_flip(&cipher_text, 16, 20, b"ab", b"cd").unwrap()[4..6], [2, 6]);
+    }
+
+    #[test]
+    fn bad_requests() {
+        let cipher_text = [0; 48];
+        match ctr_flip(&cipher_text, 0, b"abc", b"ab") {
+            Err(Error::LengthMismatch(3, 2)) => (),
+            other => panic!("expected LengthMismatch, got {:?}", other),
+        }
+        assert!(cbc_flip(&cipher_text, 16, 20, b"a", b"ab").is_err());
+
+        let everything: Vec<u8> = (0..=255).collect();
+        let walled_off = Bitflip::ctr(0).forbidding(&everything);
+        match walled_off.input_for(b"x") {
+            Err(Error::EveryByteForbidden) => (),
+            other => panic!("expected EveryByteForbidden, got {:?}", other),
+        }
+        assert!(walled_off.flip(&cipher_text, b"x").is_err());
+
+        let flipper = Bitflip::cbc(16, 0);
+        assert!(flipper.input_for(&[b'a'; 17]).is_err());
+        match Bitflip::cbc(0, 0).input_for(b"x") {
+            Err(Error::InvalidBlockSize(0)) => (),
+            other => panic!("expected InvalidBlockSize, got {:?}", other),
+        }
+        assert!(Bitflip::cbc(0, 0).flip(&cipher_text, b"x").is_err());
+        assert!(cbc_flip(&cipher_text, 0, 20, b"a", b"b").is_err());
+        assert!(flipper.inject(&|input: &[u8]| input.to_vec(), &[b'a'; 17]).is_err());
+    }
+}
@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod test_set2 {
-    use conversions::{base64_to_hex, pad_pkcs7, unpad_pkcs7, pkcs7_validate};
-    use crypter::{aes_ecb, aes_cbc, encryption_oracle, random_aes_key, random_bytes, ecb_oracle};
+    use conversions::{Base64, pad_pkcs7, unpad_pkcs7, pkcs7_validate};
+    use crypter::{aes_ecb, aes_cbc, encryption_oracle, random_aes_key, random_bytes, consistent_ecb};
+    use ecb_attack::{decrypt_suffix, detect_mode, layout};
+    use parallel::Parallel;
+    use bitflip::Bitflip;
     use text::{profile_for, sanitize_for_url};
     use openssl::symm::Mode;
 
@@ -10,21 +13,20 @@ mod test_set2 {
         // we have to take care of padding here
         // 'YELLOW SUBMARINE' -> HEX -> add 20 bytes
         let mut hex_rep = String::from("YELLOW SUBMARINEYELLOW SUBMARINEYELLOW SUBMARINE").into_bytes();
-        pad_pkcs7(&mut hex_rep, 20);
+        pad_pkcs7(&mut hex_rep, 20).unwrap();
         assert_eq!(hex_rep.len() % 20, 0);
     }
 
     #[test]
     fn challenge_10() {
-        let cipher_lines: Vec<&str> = include_str!("data/10.txt").lines().collect();
-        let original_cipher = base64_to_hex(cipher_lines.join(""));
+        let original_cipher = Base64::MIME.decode(include_str!("data/10.txt")).unwrap();
 
         let mut decrypted = vec!();
-        aes_cbc(&"YELLOW SUBMARINE".as_bytes(), &original_cipher, Some(&[0 as u8; 16]), &mut decrypted, Mode::Decrypt);
+        aes_cbc("YELLOW SUBMARINE".as_bytes(), &original_cipher, Some(&[0u8; 16]), &mut decrypted, Mode::Decrypt).unwrap();
         assert!(String::from_utf8(decrypted.clone()).unwrap().starts_with("I\'m back and I\'m ringin\'"));
 
         let mut encrypted = vec!();
-        aes_cbc(&"YELLOW SUBMARINE".as_bytes(), &decrypted, Some(&[0 as u8; 16]), &mut encrypted, Mode::Encrypt);
+        aes_cbc("YELLOW SUBMARINE".as_bytes(), &decrypted, Some(&[0u8; 16]), &mut encrypted, Mode::Encrypt).unwrap();
 
         assert_eq!(encrypted, original_cipher);
     }
@@ -33,61 +35,90 @@ mod test_set2 {
     #[test]
     fn challenge_11() {
         use rand::{thread_rng, Rng};
+        use std::cell::Cell;
+
+        // 5-10 random bytes before and after whatever goes in, the oracle remembers the mode
+        // it picked so the guess can be checked
+        let picked = Cell::new(None);
+        let oracle = |plaintext: &[u8]| {
+            let mut rng = thread_rng();
+            let (prefix_len, suffix_len) = (rng.gen_range(5,10), rng.gen_range(5,10));
+            let mut final_plaintext = rng.gen_iter::<u8>().take(prefix_len).collect::<Vec<u8>>();
+            final_plaintext.extend_from_slice(plaintext);
+            final_plaintext.extend(rng.gen_iter::<u8>().take(suffix_len));
+
+            let mut encrypted = vec!();
+            picked.set(Some(encryption_oracle(&final_plaintext, &mut encrypted).unwrap()));
+            encrypted
+        };
+
+        for _ in 0..10 {
+            let guessed = detect_mode(&oracle);
+            assert_eq!(Some(guessed.mode), picked.get());
+            assert!(guessed.confidence > 0.9);
+        }
+    }
 
-        // We just check for block duplicates 
-        let plaintext = "00000000000000000000000000000000000000000000000";
-
-        // lets first add some random prefix and suffix to it
-        let mut rng = thread_rng();
-        let (prefix_len, suffix_len) = (rng.gen_range(5,10), rng.gen_range(5,10));
-        let mut final_plaintext = rng.gen_iter::<u8>().take(prefix_len).collect::<Vec<u8>>();
-        let suffix = rng.gen_iter::<u8>().take(suffix_len).collect::<Vec<u8>>();
-
-        final_plaintext.extend_from_slice(plaintext.as_bytes());
-        final_plaintext.extend(suffix);
-
-        // then we need to pad it up
-        let mut encrypted = vec!(); 
-        let mode = encryption_oracle(&final_plaintext, &mut encrypted);
-
-        // the gist here is that we can dictate the input, so we need to use some like 00...00
-        // to detect ECB
-        let guessed_mode = if encrypted[16..32] == encrypted[32..48] {
-            "ECB"
-        } else { "CBC" };
-        assert_eq!(guessed_mode, mode);
+    // challenge 12 and 14 helper
+    fn suffix_oracle(key: &Vec<u8>, prefix: &Vec<u8>, plain_text: &[u8]) -> Vec<u8> {
+        let mut cipher_text = vec!();
+        consistent_ecb(key, prefix, plain_text, &mut cipher_text).unwrap();
+        cipher_text
     }
 
+    const SUFFIX_START: &str = "Rollin' in my 5.0\nWith my rag-top down so my hair can blow\n";
+
     #[test]
     fn challenge_12() {
-        let result = ecb_oracle(&mut vec!(), vec!());
-        println!("answer is {:?}", result);
+        let key = random_aes_key();
+        let oracle = |plain_text: &[u8]| suffix_oracle(&key, &vec!(), plain_text);
+
+        let result = String::from_utf8(decrypt_suffix(&Parallel::per_core(), &oracle).unwrap()).unwrap();
+        assert!(result.starts_with(SUFFIX_START));
     }
 
     #[test]
     fn challenge_13() {
-        use conversions::pad_pkcs7;
+        use conversions::{Padding, Pkcs7};
+        use ecb_attack::CutAndPaste;
+        use text::kv_parse;
 
-        let plaintext = profile_for("foo@bar.com", "user");
         let generated_key = random_aes_key();
+        let oracle = |email: &[u8]| {
+            let mut padded_plaintext = profile_for(&String::from_utf8_lossy(email), "user").into_bytes();
+            pad_pkcs7(&mut padded_plaintext, generated_key.len()).unwrap();
+            let mut encrypted = vec!();
+            aes_ecb(&generated_key, &padded_plaintext, None, &mut encrypted, Mode::Encrypt).unwrap();
+            encrypted
+        };
+
+        // the email is long enough to push "role=" to the end of the second block
+        let forger = CutAndPaste::new(&oracle, &Pkcs7, 16, b"email=", b"&uid=10&role=user").forbidding(b"@=&");
+        let encrypted = forger.forge(b"email=foobar123.com&uid=10&role=admin").unwrap();
 
-        let mut padded_plaintext = plaintext.as_bytes().to_owned().to_vec();
-        pad_pkcs7(&mut padded_plaintext, generated_key.len());
-        let mut encrypted = vec![0; generated_key.len() + padded_plaintext.len()];
-        aes_ecb(&generated_key, &padded_plaintext, None, &mut encrypted, Mode::Encrypt);
-
-        let mut decrypted = vec![0; generated_key.len() + padded_plaintext.len()];
-        aes_ecb(&generated_key, &encrypted, None, &mut decrypted, Mode::Decrypt);
-
-        unpad_pkcs7(&mut decrypted);
-        assert_eq!(plaintext.as_bytes(), &decrypted[..]);
+        let mut decrypted = vec!();
+        aes_ecb(&generated_key, &encrypted, None, &mut decrypted, Mode::Decrypt).unwrap();
+        Pkcs7.unpad(&mut decrypted, 16).unwrap();
+        let profile = kv_parse(String::from_utf8(decrypted).unwrap()).unwrap();
+        assert_eq!(profile["role"], "admin");
+        assert_eq!(profile["email"], "foobar123.com");
     }
 
     #[test]
     fn challenge_14() {
-        let prefix = random_bytes(16, 32).to_vec();
-        let result = ecb_oracle(&mut vec!(), prefix);
-        println!("answer is {:?}", result);
+        let key = random_aes_key();
+        let prefix = random_bytes(16, 32);
+        let oracle = |plain_text: &[u8]| suffix_oracle(&key, &prefix, plain_text);
+
+        let parallel = Parallel::per_core();
+        assert_eq!(layout(&parallel, &oracle).unwrap().prefix_len, Some(prefix.len()));
+        let result = String::from_utf8(decrypt_suffix(&parallel, &oracle).unwrap()).unwrap();
+        assert!(result.starts_with(SUFFIX_START));
+
+        // the harder version, a fresh prefix of random length on every call
+        let oracle = |plain_text: &[u8]| suffix_oracle(&key, &random_bytes(0, 40), plain_text);
+        let result = String::from_utf8(decrypt_suffix(&parallel, &oracle).unwrap()).unwrap();
+        assert!(result.starts_with(SUFFIX_START));
     }
 
     #[test]
@@ -97,9 +128,9 @@ mod test_set2 {
 
         let invalid_bytes1 = "ICE ICE BABY\x05\x05\x05\x05".as_bytes();
         
-        assert_eq!(pkcs7_validate(&valid_bytes1), true);
-        assert_eq!(pkcs7_validate(&valid_bytes2), true);
-        assert_eq!(pkcs7_validate(&invalid_bytes1), false);
+        assert!(pkcs7_validate(valid_bytes1, 16));
+        assert!(pkcs7_validate(valid_bytes2, 16));
+        assert!(!pkcs7_validate(invalid_bytes1, 16));
     }
 
     // challenge 16 helpers
@@ -109,47 +140,40 @@ mod test_set2 {
 
         let plain_text = [&prefix[..], &profile_data.as_bytes()[..], &suffix[..]].concat();
         let mut padded_input = plain_text.to_owned().to_vec();
-        pad_pkcs7(&mut padded_input, key.len());
+        pad_pkcs7(&mut padded_input, key.len()).unwrap();
 
-        aes_cbc(key, &padded_input, Some(&[0 as u8; 16]), encrypted, Mode::Encrypt)
+        aes_cbc(key, &padded_input, Some(&[0u8; 16]), encrypted, Mode::Encrypt).unwrap()
     }
 
     fn is_profile_admin(key: &[u8], cipher_text: &[u8]) -> bool {
         let mut decrypted = vec!();
-        aes_cbc(key, &cipher_text, Some(&[0 as u8; 16]), &mut decrypted, Mode::Decrypt);
-
-        let mut unpadded = decrypted.clone();
-        unpad_pkcs7(&mut unpadded);
+        aes_cbc(key, cipher_text, Some(&[0u8; 16]), &mut decrypted, Mode::Decrypt).unwrap();
 
-        let utf_str =  unsafe {
-            String::from_utf8_unchecked(unpadded)
-        }.replace("%3D", "=").replace("%3B", ";");
-        match utf_str.find("role=admin") {
-            Some(_) => true,
-            None => false,
+        // flipped cipher text can leave garbage, and garbage padding, behind
+        if unpad_pkcs7(&mut decrypted, 16).is_err() {
+            return false;
         }
+        String::from_utf8_lossy(&decrypted).contains(";admin=true;")
     }
 
     #[test]
     fn challenge_16() {
-        let plain_text1 = sanitize_for_url(&profile_for("foo@bar.com", "user"));
         let generated_key = random_aes_key();
-
-        let mut encrypted = vec!();
-        encrypt_cbc_profile(&plain_text1, &generated_key, &mut encrypted);
+        let oracle = |userdata: &[u8]| {
+            let mut encrypted = vec!();
+            encrypt_cbc_profile(&sanitize_for_url(&String::from_utf8_lossy(userdata)), &generated_key, &mut encrypted);
+            encrypted
+        };
+
+        // the escaping keeps ;admin=true; out of userdata
+        let mut encrypted = oracle(b";admin=true;");
         assert_eq!(is_profile_admin(&generated_key, &encrypted), false);
 
-
-        let plain_text2 = sanitize_for_url(&profile_for("foo@bar.com", "admin"));
-
-        let mut encrypted = vec!();
-        encrypt_cbc_profile(&plain_text2, &generated_key, &mut encrypted);
-
-        // lets flip some bit
-        encrypted[0] ^= 1;
-        encrypted[1] ^= 1;
-        encrypted[2] ^= 1;
+        // comment1=cooking%20MCs;userdata= is two blocks long
+        let flipper = Bitflip::cbc(16, 32).forbidding(b";=");
+        encrypted = flipper.inject(&oracle, b";admin=true;").unwrap();
         assert_eq!(is_profile_admin(&generated_key, &encrypted), true);
+        assert!(flipper.verify(&oracle, b";admin=true;", |c| is_profile_admin(&generated_key, c)).unwrap());
     }
 }
 
@@ -1,12 +1,12 @@
 #[cfg(test)]
 mod test_set3 {
-    use itertools::Itertools;
     use std::cmp::min;
-    use conversions::{hex_to_base64, base64_to_hex, pad_pkcs7, pkcs7_validate};
+    use conversions::{hex_to_base64, base64_to_hex, pad_pkcs7, pkcs7_validate, Pkcs7};
+    use padding_oracle;
     use crypter::{aes_cbc, random_aes_key, random_bytes, aes_ctr};
     use openssl::symm::Mode;
     use combine::{xor_each_no_wrap};
-    use crack::{find_xor_key};
+    use crack::break_fixed_nonce_ctr;
     use mersenne::MTRng;
     use rand::{thread_rng, Rng};
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -67,58 +67,6 @@ mod test_set3 {
         "QSB0ZXJyaWJsZSBiZWF1dHkgaXMgYm9ybi4="
     ];
     
-    fn is_cbc_padding_valid(key: &[u8], cipher_text: &[u8]) -> bool {
-        let mut decrypted = vec!();
-        aes_cbc(&key, &cipher_text, Some(&[0 as u8; 16]), &mut decrypted, Mode::Decrypt);
-        let retval = pkcs7_validate(&decrypted);
-        retval
-    }
-
-    // cipher is the cipher block, known_p is our currently solved plain text, known c is our
-    // current C'
-    fn solve_block(key: &[u8], cipher_1: &[u8], cipher_2: &[u8], known_p: &mut Vec<u8>) {
-        let block_size = cipher_2.len();
-        let mut known_c = vec!();
-
-        for idx in (0..16).rev() {
-            // we need to solve each byte now
-            for _c in 0..256 {
-                let c_prime = _c as u8;
-                let padding = (block_size - idx) as u8;
-
-                let zero_prefix = vec![0; block_size - known_c.len() - 1];
-                let suffix: Vec<u8> = known_c.iter().map(|k| k ^ padding).collect();
-                let test_cipher = [&zero_prefix, &vec![c_prime], &suffix, cipher_2].concat();
-                if is_cbc_padding_valid(&key, &test_cipher) {
-                    let c1 = cipher_1[idx];
-                    let new_c_prime = padding ^ c_prime;
-                    let last_byte =  new_c_prime ^ c1;
-
-                    known_c.insert(0, new_c_prime);
-                    known_p.insert(0, last_byte);
-                    break;
-                }
-            }           
-        }
-    }
-
-    fn cbc_padding_attack(key: &[u8], iv: &[u8], encrypted: &[u8]) -> Vec<u8>{
-        let combined = &[iv, encrypted].concat();
-        let mut chunks = combined.chunks(16).rev();
-        let mut retval: Vec<u8> = vec!();
-
-        let mut last = chunks.next().unwrap();
-        while let Some(iv) = chunks.next() {
-            let mut known_p = vec!();
-            solve_block(key, &iv, &last, &mut known_p);
-
-            known_p.extend(retval);
-            retval = known_p;
-            last = iv;
-        }
-        retval
-    }
-
     #[test]
     fn challenge_17() {
         // 1. randomly pick
@@ -127,54 +75,39 @@ mod test_set3 {
         let iv = random_aes_key();
 
         let rand_idx = rng.gen_range(0, CH_17_STRS.len()); 
-        let rand_str = base64_to_hex(String::from(CH_17_STRS[rand_idx]));
+        let rand_str = base64_to_hex(CH_17_STRS[rand_idx]).unwrap();
         let generated_key = random_aes_key();
 
         let mut padded_plaintext = rand_str.clone();
-        pad_pkcs7(&mut padded_plaintext, generated_key.len());
+        pad_pkcs7(&mut padded_plaintext, generated_key.len()).unwrap();
 
         let mut encrypted = vec!();
-        aes_cbc(&generated_key, &padded_plaintext, Some(&iv), &mut encrypted, Mode::Encrypt);
-        assert_eq!(cbc_padding_attack(&generated_key, &iv, &encrypted), padded_plaintext);
+        aes_cbc(&generated_key, &padded_plaintext, Some(&iv), &mut encrypted, Mode::Encrypt).unwrap();
+
+        // the server only reveals whether the padding checked out
+        let oracle = |iv: &[u8], cipher_text: &[u8]| {
+            let mut decrypted = vec!();
+            aes_cbc(&generated_key, cipher_text, Some(iv), &mut decrypted, Mode::Decrypt).is_ok()
+                && pkcs7_validate(&decrypted, 16)
+        };
+        assert_eq!(padding_oracle::decrypt(&oracle, &Pkcs7, 16, &iv, &encrypted).unwrap(), rand_str);
     }
 
     #[test]
     fn challenge_18() {
-        let encrypted = base64_to_hex("L77na/nrFsKvynd6HzOoG7GHTLXsTVu9qvY/2syLXzhPweyyMTJULu/6/kXX0KSvoOLSFQ==".to_string());
+        let encrypted = base64_to_hex("L77na/nrFsKvynd6HzOoG7GHTLXsTVu9qvY/2syLXzhPweyyMTJULu/6/kXX0KSvoOLSFQ==").unwrap();
         let key = "YELLOW SUBMARINE";
         let nonce = 0u64;
 
         let mut decrypted = vec!();
-        aes_ctr(key.as_bytes(), &encrypted, nonce, &mut decrypted);
+        aes_ctr(key.as_bytes(), &encrypted, nonce, &mut decrypted).unwrap();
 
         let mut reencrypted = vec!();
-        aes_ctr(key.as_bytes(), &decrypted, nonce, &mut reencrypted);
+        aes_ctr(key.as_bytes(), &decrypted, nonce, &mut reencrypted).unwrap();
 
         assert_eq!(encrypted, reencrypted);
     }
 
-    fn get_candidates(ciphers: &Vec<Vec<u8>>, idx: usize) -> Vec<u8> {
-        use std::collections::HashSet;
-        use std::iter::FromIterator;
-
-        let valid_chars: HashSet<&u8> = HashSet::from_iter("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ ,".as_bytes().iter());
-        // grab the u8 values that decrypts out into an actual ascii character
-        let mut retval = vec!();
-        for ch in 0..256u16 {
-            if ciphers.iter().map(|cipher| cipher[idx] ^ ch as u8).all(|c| valid_chars.contains(&c)) {
-                retval.push(ch as u8);
-            }
-        }
-
-        return retval;
-    }
-
-    fn extend_key(current_keys: &mut Vec<u8>, ciphers: &[u8], guess: &[u8]){
-        let key_len = current_keys.len();
-        let xored: Vec<u8> = (0..guess.len()).map(|idx| guess[idx] ^ ciphers[key_len+idx]).collect();
-        current_keys.extend(xored);
-    }
-
     #[test]
     fn challenge_19() {
         let key = [163, 201, 231, 237, 109, 90, 85, 30, 172, 21, 226, 175, 180, 36, 169, 123];
@@ -183,64 +116,44 @@ mod test_set3 {
         let mut encrypted_vec = vec!();
         for plain_text in CH_19_STRS {
             let mut decrypted = vec!();
-            aes_ctr(&key, &base64_to_hex(plain_text.to_string()), nonce, &mut decrypted);
+            aes_ctr(&key, &base64_to_hex(plain_text).unwrap(), nonce, &mut decrypted).unwrap();
             encrypted_vec.push(decrypted);
         }
 
-        let char_candidates = (0..10).map(|idx| get_candidates(&encrypted_vec, idx));
-        let mut prods = char_candidates.multi_cartesian_product();
-        let mut current_keys = prods.next().unwrap();
-
-        // manual edit
-        extend_key(&mut current_keys, &encrypted_vec[1], "h ".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[3], "entury ".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[5], "ss ".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[3], "se".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[5], "rds".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[0], " ".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[29], "ght".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[4], " ".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[27], "d".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[4], "ead".as_bytes());
-        extend_key(&mut current_keys, &encrypted_vec[37], "n,".as_bytes());
-
-        let keys_len = current_keys.len();
-
-        for (idx, cipher) in encrypted_vec.iter().enumerate(){
-            let c_len = cipher.len();
-            let t1 = xor_each_no_wrap(&cipher[..min(keys_len, c_len)], &current_keys[..min(c_len, keys_len)]);
-            let decrypted = [&t1[..], &cipher[min(keys_len, c_len)..]].concat();
-            assert_eq!(hex_to_base64(&decrypted), CH_19_STRS[idx]);
+        let (key_stream, plain_texts) = break_fixed_nonce_ctr(&encrypted_vec);
+        assert_eq!(key_stream.len(), encrypted_vec.iter().map(|c| c.len()).max().unwrap());
+
+        // only the ends of the longest few lines can be off
+        for (idx, decrypted) in plain_texts.iter().enumerate() {
+            let original = base64_to_hex(CH_19_STRS[idx]).unwrap();
+            let sure = min(original.len(), 30);
+            assert_eq!(decrypted[..sure], original[..sure]);
         }
+        let exact = plain_texts.iter().enumerate().filter(|&(idx, p)| hex_to_base64(p) == CH_19_STRS[idx]).count();
+        assert!(exact >= 35, "{} lines decrypted exactly", exact);
     }
 
     #[test]
     fn challenge_20() {
-        // no need to finish this one, it does need manual input to polish the rest, but it looks pretty good without.
-        let plain_text: Vec<Vec<u8>> = include_str!("data/20.txt").lines().map(|l| base64_to_hex(l.to_string())).collect();
+        let plain_text: Vec<Vec<u8>> = include_str!("data/20.txt").lines().map(|l| base64_to_hex(l).unwrap()).collect();
         let key = random_aes_key();
         let nonce = 0u64;
 
         let mut ciphers = vec!();
         for line in plain_text.iter() {
             let mut decrypted = vec!();
-            aes_ctr(&key, &line, nonce, &mut decrypted);
+            aes_ctr(&key, line, nonce, &mut decrypted).unwrap();
             ciphers.push(decrypted);
         }
 
+        // the challenge truncates to the shortest line, the rest gets broken as well
         let min_len = ciphers.iter().min_by_key(|cipher| cipher.len()).unwrap().len();
-
-        let truncated_ciphers: Vec<Vec<u8>> = ciphers.iter().map(|cipher| cipher[..min_len].to_vec()).into_iter().collect();
-        let zipped_ciphers = (0..min(truncated_ciphers.len() as u8, min_len as u8)).map(|idx| truncated_ciphers.iter().map(|cipher| cipher[idx as usize]).collect::<Vec<u8>>());
-        let key_stream: Vec<u8> = zipped_ciphers.map(|cipher| find_xor_key(&cipher)).collect();
-
-        let keys_len = key_stream.len();
-
-        for cipher in ciphers.iter(){
-            let c_len = cipher.len();
-            let t1 = xor_each_no_wrap(&cipher[..min(keys_len, c_len)], &key_stream[..min(c_len, keys_len)]);
-            let _decrypted = [&t1[..], &cipher[min(keys_len, c_len)..]].concat();
+        let (_, decrypted) = break_fixed_nonce_ctr(&ciphers);
+        for (original, cracked) in plain_text.iter().zip(decrypted.iter()) {
+            assert_eq!(cracked[..min_len], original[..min_len]);
         }
+        assert!(decrypted[0].starts_with(b"I'm rated \"R\"...this is a warning"));
+        assert!(plain_text.iter().zip(decrypted.iter()).filter(|&(o, c)| o == c).count() >= 50);
     }
 
     const TEST_VECTOR: [u32; 20] = [
@@ -1,10 +1,12 @@
 #[cfg(test)]
 mod test_set4 {
     use conversions::{base64_to_hex, pad_pkcs7, unpad_pkcs7};
-    use crypter::{aes_cbc, random_aes_key, random_bytes, aes_ctr};
+    use crypter::{aes_cbc, random_aes_key, random_bytes, aes_ctr, Ctr};
+    use aes::Aes;
     use openssl::symm::Mode;
-    use combine::{xor_each_no_wrap};
-    use text::{profile_for, sanitize_for_url};
+    use text::sanitize_for_url;
+    use bitflip::Bitflip;
+    use iv_key::{lint_iv_equals_key, recover_key};
     use sha1::{SHA1, generate_sha1_padding};
     use md4::{MD4, generate_md4_padding};
     use std::thread;
@@ -15,32 +17,39 @@ mod test_set4 {
     use std::collections::BinaryHeap;
 
 
-    fn edit(ciphertext: &[u8], new_text: &[u8], key: &[u8], nonce: u64, offset: u8) -> Vec<u8> {
-        let mut output = vec!();
-        let offset_input = &[&vec![0; offset as usize], new_text].concat();
-        aes_ctr(key, offset_input, nonce, &mut output);
-        [&ciphertext[..offset as usize], &output[offset as usize..]].concat()
-    }
-
     #[test]
     fn challenge_25() {
         // this question was poorly designed, I assumed it required some kind of brute force
         // however since ctr is super symmetric, we can crack the plaintext just by giving it back the ciphertext
-        let plain_text: Vec<Vec<u8>> = include_str!("data/25.txt").lines().map(|l| base64_to_hex(l.to_string())).collect();
+        let plain_text: Vec<Vec<u8>> = include_str!("data/25.txt").lines().map(|l| base64_to_hex(l).unwrap()).collect();
         let key = random_aes_key();
         let nonce = 0u64;
 
         let mut ciphers = vec!();
         for line in plain_text.iter() {
             let mut decrypted = vec!();
-            aes_ctr(&key, &line, nonce, &mut decrypted);
+            aes_ctr(&key, line, nonce, &mut decrypted).unwrap();
             ciphers.push(decrypted);
         }
 
+        // the attacker only gets to call edit, the key stays behind the closure
+        let mut context = Ctr::new(Aes::new(&key).unwrap(), nonce).unwrap();
+        let mut edit = |cipher_text: &[u8], offset: u64, new_text: &[u8]| -> Vec<u8> {
+            let mut edited = cipher_text.to_vec();
+            context.edit(&mut edited, offset, new_text).unwrap();
+            edited
+        };
+
         let plain_text1 = plain_text.first().unwrap();
         let cipher1 = ciphers.first().unwrap();
-        let cracked = edit(cipher1, cipher1, &key, nonce, 0);
+        let cracked = edit(cipher1, 0, cipher1);
         assert_eq!(cracked, *plain_text1);
+
+        // random access works just as well, writing the cipher text over itself at any offset
+        // hands back the plain text for that range
+        let offset = cipher1.len() as u64 / 2;
+        let cracked_tail = edit(cipher1, offset, &cipher1[offset as usize..]);
+        assert_eq!(cracked_tail[offset as usize..], plain_text1[offset as usize..]);
     }
 
     // challenge 26
@@ -50,47 +59,40 @@ mod test_set4 {
 
         let plain_text = [&prefix[..], &profile_data.as_bytes()[..], &suffix[..]].concat();
         let mut padded_input = plain_text.to_owned().to_vec();
-        pad_pkcs7(&mut padded_input, key.len());
+        pad_pkcs7(&mut padded_input, key.len()).unwrap();
 
-        aes_ctr(key, &padded_input, 0u64, encrypted)
+        aes_ctr(key, &padded_input, 0u64, encrypted).unwrap()
     }
 
     fn is_profile_admin(key: &[u8], cipher_text: &[u8]) -> bool {
         let mut decrypted = vec!();
-        aes_ctr(key, &cipher_text, 0u64, &mut decrypted);
+        aes_ctr(key, cipher_text, 0u64, &mut decrypted).unwrap();
 
-        let mut unpadded = decrypted.clone();
-        unpad_pkcs7(&mut unpadded);
-
-        let utf_str =  unsafe {
-            String::from_utf8_unchecked(unpadded)
-        }.replace("%3D", "=").replace("%3B", ";");
-        match utf_str.find("role=admin") {
-            Some(_) => true,
-            None => false,
+        // flipped cipher text can leave garbage, and garbage padding, behind
+        if unpad_pkcs7(&mut decrypted, 16).is_err() {
+            return false;
         }
+        String::from_utf8_lossy(&decrypted).contains(";admin=true;")
     }
 
     #[test]
     fn challenge_26() {
-        let plain_text1 = sanitize_for_url(&profile_for("foo@bar.com", "user"));
         let generated_key = random_aes_key();
-
-        let mut encrypted = vec!();
-        encrypt_ctr_profile(&plain_text1, &generated_key, &mut encrypted);
+        let oracle = |userdata: &[u8]| {
+            let mut encrypted = vec!();
+            encrypt_ctr_profile(&sanitize_for_url(&String::from_utf8_lossy(userdata)), &generated_key, &mut encrypted);
+            encrypted
+        };
+
+        // the escaping keeps ;admin=true; out of userdata
+        let mut encrypted = oracle(b";admin=true;");
         assert_eq!(is_profile_admin(&generated_key, &encrypted), false);
 
-
-        let plain_text2 = sanitize_for_url(&profile_for("foo@bar.com", "admin"));
-
-        let mut encrypted = vec!();
-        encrypt_ctr_profile(&plain_text2, &generated_key, &mut encrypted);
-
-        // lets flip some bit
-        encrypted[0] ^= 1;
-        encrypted[1] ^= 1;
-        encrypted[2] ^= 1;
+        // comment1=cooking%20MCs;userdata= is 32 bytes
+        let flipper = Bitflip::ctr(32).forbidding(b";=");
+        encrypted = flipper.inject(&oracle, b";admin=true;").unwrap();
         assert_eq!(is_profile_admin(&generated_key, &encrypted), true);
+        assert!(flipper.verify(&oracle, b";admin=true;", |c| is_profile_admin(&generated_key, c)).unwrap());
     }
 
     // challenge 27
@@ -100,36 +102,42 @@ mod test_set4 {
 
         let plain_text = [&prefix[..], &profile_data.as_bytes()[..], &suffix[..]].concat();
         let mut padded_input = plain_text.to_owned().to_vec();
-        pad_pkcs7(&mut padded_input, key.len());
+        pad_pkcs7(&mut padded_input, key.len()).unwrap();
 
-        aes_cbc(key, &padded_input, Some(key), encrypted, Mode::Encrypt)
+        aes_cbc(key, &padded_input, Some(key), encrypted, Mode::Encrypt).unwrap()
     }
 
     #[test]
     fn challenge_27() {
-        let plain_text = sanitize_for_url(&profile_for("foo@ab.com", "usernameishigh"));
         let generated_key = random_aes_key();
-
-        let mut encrypted = vec!();
-        encrypt_cbc_profile(&plain_text, &generated_key, &mut encrypted);
-
-        // attacker: zero out the second block and replace the third with the first
-        let attacked_cipher = [&encrypted[..16], &[0; 16], &encrypted[..16]].concat();
-
-        // now the receiver attempts to decrypt, then raise an error if an ascii is too high in value
-        let mut decrypted = vec!();
-        aes_cbc(&generated_key, &attacked_cipher, Some(&generated_key), &mut decrypted, Mode::Decrypt);
-        let contains_error = decrypted.iter().any(|&c| c >= 127);
-
-        assert!(contains_error);
-
-        // now the attacker, posing as the system receives the plaintext as the invalidated error
-        let key = xor_each_no_wrap(&decrypted[..16], &decrypted[32..]);
-        assert_eq!(key, generated_key);
+        let encrypt = |userdata: &[u8]| {
+            let mut encrypted = vec!();
+            encrypt_cbc_profile(&sanitize_for_url(&String::from_utf8_lossy(userdata)), &generated_key, &mut encrypted);
+            encrypted
+        };
+
+        // the receiver decrypts, then raises an error quoting the plain text if an ascii is
+        // too high in value
+        let decrypt = |cipher_text: &[u8]| {
+            let mut decrypted = vec!();
+            aes_cbc(&generated_key, cipher_text, Some(&generated_key), &mut decrypted, Mode::Decrypt).unwrap();
+            unpad_pkcs7(&mut decrypted, 16).map_err(|_| vec!())?;
+            if decrypted.iter().any(|&c| c >= 127) { Err(decrypted) } else { Ok(()) }
+        };
 
         // because it takes c2 to get p3 in the decryption process, when we zero out c2, AND
         // since we replace c3 with c1, p1 is c1 is decrypted with the IV, and c3 is decrypted with zero bytes
         // meaning p'1 ^ p'3 yields the IV, or the key!!!
+        assert_eq!(recover_key(16, &encrypt, &decrypt).unwrap(), generated_key);
+
+        // both ends of this challenge get flagged, a zero IV doesn't
+        let source = r#"
+            aes_cbc(key, &padded_input, Some(key), encrypted, Mode::Encrypt).unwrap()
+            aes_cbc(&generated_key, cipher_text, Some(&generated_key), &mut decrypted, Mode::Decrypt).unwrap();
+            aes_cbc(&key, &cipher, Some(&[0; 16]), &mut decrypted, Mode::Decrypt).unwrap();
+        "#;
+        let reused: Vec<String> = lint_iv_equals_key(source).into_iter().map(|r| r.key).collect();
+        assert_eq!(reused, ["key", "generated_key"]);
     }
 
     // challenge 28+
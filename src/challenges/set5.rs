@@ -52,8 +52,8 @@ fffffffffffff";
         let mut padded_msg = msg.clone(); 
 
         let mut msg_encrypted = vec!();
-        pad_pkcs7(&mut padded_msg, key.len());
-        aes_cbc(key, &padded_msg, Some(&iv), &mut msg_encrypted, Mode::Encrypt);
+        pad_pkcs7(&mut padded_msg, key.len()).unwrap();
+        aes_cbc(key, &padded_msg, Some(&iv), &mut msg_encrypted, Mode::Encrypt).unwrap();
         msg_encrypted.extend(iv);
         (msg_encrypted, msg)
     }
@@ -63,8 +63,8 @@ fffffffffffff";
         let (encrypted, iv) = (&encrypted[..encrypted.len()-16], &encrypted[encrypted.len()-16..]);
 
         let mut msg_decrypted = vec!();
-        aes_cbc(&session_key[..16], &encrypted, Some(&iv), &mut msg_decrypted, Mode::Decrypt);
-        unpad_pkcs7(&mut msg_decrypted);
+        aes_cbc(&session_key[..16], encrypted, Some(iv), &mut msg_decrypted, Mode::Decrypt).unwrap();
+        unpad_pkcs7(&mut msg_decrypted, 16).unwrap();
         msg_decrypted
     }
 
@@ -141,7 +141,7 @@ fffffffffffff";
 
     #[test]
     fn challenge_39() {
-        println!("rsa {:?}", rsa_keygen());
+        println!("rsa {:?}", rsa_keygen().unwrap());
     }
     
 }
@@ -1,9 +1,14 @@
 // reference: https://github.com/jakerr/cryptopals-rust/blob/master/src/conversions.rs
 
 use std::iter::Iterator;
+use std::io::{self, Read};
+use std::result;
+use rand::{thread_rng, Rng};
+use error::{Error, PaddingError, Result};
 
 
-const BASE_64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE_64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE_64_URL: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
 
 fn hex_to_char(short: u8) -> char {
     match short {
@@ -14,14 +19,20 @@ fn hex_to_char(short: u8) -> char {
 }
 
 
-fn char_to_hex(c: char) -> u8 {
+fn char_to_hex(c: char) -> Result<u8> {
     match c {
-        '0'...'9' => (c as u8 - '0' as u8),
-        'a'...'f' => 10 + (c as u8 - 'a' as u8),
-        _ => panic!("char_to_hex only converts char values between '0' and 'f'"),
+        '0'..='9' => Ok(c as u8 - b'0'),
+        'a'..='f' => Ok(10 + (c as u8 - b'a')),
+        'A'..='F' => Ok(10 + (c as u8 - b'A')),
+        _ => Err(Error::InvalidHexChar(c)),
     }
 }
 
+// the whitespace MIME and PEM encoders wrap their output with
+fn is_wrap_space(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\r' || c == b'\n'
+}
+
 
 pub fn hex_to_string(hex: &[u8]) -> String {
     hex.iter().map(|x| {
@@ -34,27 +45,48 @@ pub fn hex_to_string(hex: &[u8]) -> String {
 }
 
 
-pub fn string_to_hex(string: &str) -> Vec<u8> {
-    let mut v = Vec::new();
-    let mut cs = string.chars();
-    loop {
-        let pair = (cs.next(), cs.next());
-        match pair {
-            (Some(h), Some(l)) => {
-            	// each char needs to be converted into hex value
-            	let h = char_to_hex(h);
-            	let l = char_to_hex(l);
+// strict, every character has to be a hex digit in either case
+pub fn string_to_hex(string: &str) -> Result<Vec<u8>> {
+    decode_all(DecodeState::Hex(HexState::new(false)), string)
+}
 
-            	// combine the two to make a byte, packed u8
-            	let byte = (h << 4) | l;
-            	v.push(byte);
-            },
-            (Some(_), None) => panic!("Strings need pairs (even numbers of characters to be \
-            considered valid hex"),
-            _ => break,
+// same as string_to_hex, but skips whitespace so wrapped dumps can be fed in as is
+pub fn string_to_hex_lenient(string: &str) -> Result<Vec<u8>> {
+    decode_all(DecodeState::Hex(HexState::new(true)), string)
+}
+
+
+// hex decoding one character at a time
+struct HexState {
+    high: Option<u8>,
+    lenient: bool,
+}
+
+impl HexState {
+    fn new(lenient: bool) -> HexState {
+        HexState { high: None, lenient }
+    }
+
+    fn push(&mut self, c: u8, out: &mut Vec<u8>) -> Result<()> {
+        if self.lenient && is_wrap_space(c) {
+            return Ok(());
+        }
+
+        let nibble = char_to_hex(c as char)?;
+        match self.high.take() {
+            // combine the two to make a byte, packed u8
+            Some(h) => out.push((h << 4) | nibble),
+            None => self.high = Some(nibble),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.high {
+            Some(_) => Err(Error::OddHexLength),
+            None => Ok(()),
         }
     }
-    v
 }
 
 
@@ -67,18 +99,18 @@ struct Bits<'a> {
     // idx of the bit id we are on
     bidx: usize,
 
-    // size of the bits stride
+    // size of the bits stride, up to 32 bits
     stride: usize,
 }
 
 
 impl <'a>Bits<'a> {
     fn new(hex: &'a [u8], stride: usize) -> Bits<'a> {
-    	assert!(stride <= 8);
+    	assert!(stride > 0 && stride <= 32);
     	Bits {hex, stride, idx: 0, bidx: 0}
     }
 
-    // bite ensures that we return the next n bits, that is less or equal to 8 bits
+    // bite returns up to the next n bits, but never crosses a byte boundary
     // meaning that bite only processes 1 byte at a time
     fn bite(&mut self, bits: usize) -> (usize, u8) {
     	// lets figure out how many bits are left remaining in a 8 bits max context
@@ -115,134 +147,1019 @@ impl <'a>Bits<'a> {
     }
 }
 
+// yields stride bits at a time, big endian, the last value is padded with zero bits
 impl <'a>Iterator for Bits<'a> {
-	type Item = u8;
+	type Item = u32;
 
-	fn next(&mut self) -> Option<u8> {
+	fn next(&mut self) -> Option<u32> {
 		let mut need = self.stride;
-		let (high_read, high_byte) = self.bite(need);
-		if high_read == 0 {
+		let mut value = 0u32;
+
+		// keep biting until the stride is full or we run out of bytes
+		while need > 0 {
+			let (read, byte) = self.bite(need);
+			if read == 0 {
+				break;
+			}
+			value = (value << read) | u32::from(byte);
+			need -= read;
+		}
+
+		if need == self.stride {
 			return None;
 		}
+		Some(value << need)
+	}
+}
 
-		need = need - high_read;
 
-		let (_, low_byte) = if need > 0 {
-			self.bite(need)
-		} else {
-			(0, 0)
-		};
+/// A base64 flavour: the alphabet, whether the encoder pads, and whether the decoder is
+/// lenient. Strict decoding rejects whitespace and, for padded flavours, missing padding.
+/// Lenient decoding skips MIME line breaks and accepts input with or without padding.
+/// Characters outside the alphabet are an error either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Base64 {
+    alphabet: &'static [u8],
+    pad: bool,
+    lenient: bool,
+}
 
-		Some((high_byte << need) | low_byte)
-	}
+impl Base64 {
+    pub const STANDARD: Base64 = Base64 { alphabet: BASE_64, pad: true, lenient: false };
+    /// standard alphabet, line wrapped
+    pub const MIME: Base64 = Base64 { alphabet: BASE_64, pad: true, lenient: true };
+    pub const URL_SAFE: Base64 = Base64 { alphabet: BASE_64_URL, pad: true, lenient: false };
+    pub const URL_SAFE_NO_PAD: Base64 = Base64 { alphabet: BASE_64_URL, pad: false, lenient: false };
+
+    pub fn lenient(self) -> Base64 {
+        Base64 { lenient: true, ..self }
+    }
+
+    pub fn encode(&self, hex: &[u8]) -> String {
+    	// we need to take strides of 4x6 bits
+    	// each hex is 4 bits in representation, we need to compact u8 together
+    	let mut b = Bits::new(hex, 6);
+    	let mut s = String::new();
+
+    	loop {
+    	    let set = (b.next(), b.next(), b.next(), b.next());
+    	    match set {
+    	        (None, _, _, _) => break,
+    	        (Some(h1), Some(h2), m, l) => {
+    	        	s.push(self.alphabet[h1 as usize] as char);
+    	        	s.push(self.alphabet[h2 as usize] as char);
+    	        	for sextet in &[m, l] {
+    	        	    match *sextet {
+    	        	        Some(x) => s.push(self.alphabet[x as usize] as char),
+    	        	        None if self.pad => s.push('='),
+    	        	        None => (),
+    	        	    }
+    	        	}
+    	        },
+    	        _ => unreachable!(),
+    	    }
+    	}
+    	s
+    }
+
+    pub fn decode(&self, string: &str) -> Result<Vec<u8>> {
+        decode_all(DecodeState::Base64(Base64State::new(*self)), string)
+    }
+
+    /// Decodes base64 pulled from `inner` as it is read
+    pub fn reader<R: Read>(&self, inner: R) -> DecodeReader<R> {
+        DecodeReader::new(inner, DecodeState::Base64(Base64State::new(*self)))
+    }
 }
 
 
 pub fn hex_to_base64(hex: &[u8]) -> String {
-	// we need to take strides of 4x6 bits 
-	// each hex is 4 bits in representation, we need to compact u8 together 
-	let mut b = Bits::new(hex, 6);
-	let mut s = String::new();
-
-	loop {
-	    let set = (b.next(), b.next(), b.next(), b.next());
-	    match set {
-	        (None, _, _, _) => break,
-	        (Some(h1), Some(h2), m, l) => {
-	        	s.push(BASE_64[h1 as usize] as char);
-	        	s.push(BASE_64[h2 as usize] as char);
-	        	match m {
-	        	    Some(m) => s.push(BASE_64[m as usize] as char),
-	        	    _ => s.push('='),
-	        	};
-	        	match l {
-	        	    Some(l) => s.push(BASE_64[l as usize] as char),
-	        	    _ => s.push('='),
-	        	};
-	        },
-	        _ => unreachable!(),
-	    }
-	}
-	s
-}
-
-
-fn base64_inverse(c: char) -> Option<u8> {
-	BASE_64.iter().position(|x| c == *x as char).map(|x| x as u8)
-}
-
-
-// Takes a base64 encoded string and returns the vector of bytes that it decodes to 
-pub fn base64_to_hex(string: String) -> Vec<u8> {
-	let mut v = Vec::new();
-	let mut chars = string.chars();
-	loop {
-	    let set = (chars.next(), chars.next(), chars.next(), chars.next());
-	    match set {
-	        (None, _, _, _) => break,
-	        (Some(a), Some(b), Some(c), Some(d)) => {
-	        	let mut h = base64_inverse(a).unwrap() << 2;
-	        	let mut m = base64_inverse(b).unwrap();
-	        	h |= m >> 4;
-	        	m <<= 4;
-	        	v.push(h);
-
-	        	let mut l = 0;
-	        	match base64_inverse(c) {
-	        	    Some(c) => {
-	        	    	m |= c >> 2;
-	        	    	l = c << 6;
-	        	    	v.push(m)
-	        	    },
-	        	    _ => (),
-	        	}
-
-	        	match base64_inverse(d) {
-	        	    Some(d) => {
-	        	    	l |= d;
-	        	    	v.push(l)
-	        	    },
-	        	    _ => (),
-	        	}
-	        },
-	        _ => panic!("Invalid base64. Inproperly padded"),
-	    }
-	}
-	v
+    Base64::STANDARD.encode(hex)
 }
 
 
-// if valid returns the length to truncate, else return original length
-// should not call this if we know its not padded
-fn pkcs7_truncate_len(text: &[u8]) -> usize{
-    let text_len = text.len();
-    let last = text[text.len() - 1];
+// Takes a base64 encoded string and returns the vector of bytes that it decodes to
+pub fn base64_to_hex(string: &str) -> Result<Vec<u8>> {
+    Base64::STANDARD.decode(string)
+}
+
+
+// base64 decoding one character at a time, sextets are collected in acc until a group of
+// four is complete
+struct Base64State {
+    config: Base64,
+    acc: u32,
+    sextets: usize,
+    padding: usize,
+    // set once a padded group is complete, nothing but whitespace can follow
+    done: bool,
+}
+
+impl Base64State {
+    fn new(config: Base64) -> Base64State {
+        Base64State { config, acc: 0, sextets: 0, padding: 0, done: false }
+    }
+
+    fn push(&mut self, c: u8, out: &mut Vec<u8>) -> Result<()> {
+        if self.config.lenient && is_wrap_space(c) {
+            return Ok(());
+        }
+        if self.done {
+            return Err(Error::InvalidBase64Char(c as char));
+        }
+
+        if c == b'=' {
+            // padding can only stand in for the last one or two characters of a group
+            if self.sextets < 2 || !(self.config.pad || self.config.lenient) {
+                return Err(Error::InvalidBase64Char('='));
+            }
+            self.padding += 1;
+            if self.sextets + self.padding == 4 {
+                self.flush(out);
+                self.done = true;
+            }
+            return Ok(());
+        }
+
+        let value = match self.config.alphabet.iter().position(|&x| x == c) {
+            Some(value) if self.padding == 0 => value as u32,
+            _ => return Err(Error::InvalidBase64Char(c as char)),
+        };
+        self.acc = (self.acc << 6) | value;
+        self.sextets += 1;
+        if self.sextets == 4 {
+            self.flush(out);
+        }
+        Ok(())
+    }
+
+    // every sextet after the first completes another byte
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        let bits = self.sextets * 6;
+        for i in 1..self.sextets {
+            out.push((self.acc >> (bits - 8 * i)) as u8);
+        }
+        self.acc = 0;
+        self.sextets = 0;
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        match self.sextets {
+            0 => Ok(()),
+            // a single sextet doesn't even make up a byte
+            1 => Err(Error::InvalidBase64Length),
+            _ if self.padding > 0 || (self.config.pad && !self.config.lenient) => Err(Error::InvalidBase64Length),
+            _ => {
+                self.flush(out);
+                Ok(())
+            },
+        }
+    }
+}
+
+
+enum DecodeState {
+    Hex(HexState),
+    Base64(Base64State),
+}
+
+impl DecodeState {
+    fn push(&mut self, c: u8, out: &mut Vec<u8>) -> Result<()> {
+        match *self {
+            DecodeState::Hex(ref mut state) => state.push(c, out),
+            DecodeState::Base64(ref mut state) => state.push(c, out),
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        match *self {
+            DecodeState::Hex(ref mut state) => state.finish(),
+            DecodeState::Base64(ref mut state) => state.finish(out),
+        }
+    }
+}
+
+fn decode_all(mut state: DecodeState, string: &str) -> Result<Vec<u8>> {
+    let mut v = Vec::with_capacity(string.len());
+    for &c in string.as_bytes() {
+        state.push(c, &mut v)?;
+    }
+    state.finish(&mut v)?;
+    Ok(v)
+}
+
+
+/// Incremental hex or base64 decoding over a reader, so large encoded files never have to
+/// be loaded whole. Malformed input surfaces as an `InvalidData` io error.
+pub struct DecodeReader<R: Read> {
+    inner: R,
+    state: DecodeState,
+    decoded: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    fn new(inner: R, state: DecodeState) -> DecodeReader<R> {
+        DecodeReader { inner, state, decoded: vec!(), pos: 0, eof: false }
+    }
+
+    pub fn hex(inner: R, lenient: bool) -> DecodeReader<R> {
+        DecodeReader::new(inner, DecodeState::Hex(HexState::new(lenient)))
+    }
+
+    // decodes the next chunk of the inner reader, false once everything is decoded
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0u8; 4096];
+        self.decoded.clear();
+        self.pos = 0;
+
+        while self.decoded.is_empty() && !self.eof {
+            let count = self.inner.read(&mut chunk)?;
+            let result = if count == 0 {
+                self.eof = true;
+                self.state.finish(&mut self.decoded)
+            } else {
+                chunk[..count].iter().try_for_each(|&c| self.state.push(c, &mut self.decoded))
+            };
+            result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(!self.decoded.is_empty())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.decoded.len() && !self.fill()? {
+            return Ok(0);
+        }
+
+        let count = buf.len().min(self.decoded.len() - self.pos);
+        buf[..count].copy_from_slice(&self.decoded[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+
+const BASE_32: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const BASE_32_HEX: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// RFC 4648 base32, in either the standard or the extended hex alphabet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Base32 {
+    alphabet: &'static [u8],
+    pad: bool,
+}
+
+impl Base32 {
+    pub const STANDARD: Base32 = Base32 { alphabet: BASE_32, pad: true };
+    pub const HEX: Base32 = Base32 { alphabet: BASE_32_HEX, pad: true };
+
+    pub fn no_pad(self) -> Base32 {
+        Base32 { pad: false, ..self }
+    }
+
+    pub fn encode(&self, hex: &[u8]) -> String {
+        let mut s: String = Bits::new(hex, 5).map(|x| self.alphabet[x as usize] as char).collect();
+        if self.pad {
+            while !s.len().is_multiple_of(8) {
+                s.push('=');
+            }
+        }
+        s
+    }
+
+    pub fn decode(&self, string: &str) -> Result<Vec<u8>> {
+        let data = string.trim_end_matches('=');
+        let pad_len = string.len() - data.len();
+        // the characters left over by 1, 2, 3 or 4 trailing bytes, and the padding RFC 4648
+        // puts after each of them
+        let expected_pad = match data.len() % 8 {
+            0 => 0,
+            2 => 6,
+            4 => 4,
+            5 => 3,
+            7 => 1,
+            _ => return Err(Error::InvalidBase32Length),
+        };
+        // padding, when there is any, has to be exactly what encode would have written
+        if (self.pad || pad_len > 0) && pad_len != expected_pad {
+            return Err(Error::InvalidBase32Length);
+        }
+
+        let mut v = Vec::with_capacity(data.len() * 5 / 8);
+        let (mut acc, mut bits) = (0u32, 0);
+        for c in data.chars() {
+            let value = self.alphabet.iter().position(|&x| x as char == c).ok_or(Error::InvalidBase32Char(c))?;
+            acc = (acc << 5) | value as u32;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                v.push((acc >> bits) as u8);
+                acc &= (1 << bits) - 1;
+            }
+        }
+        Ok(v)
+    }
+}
+
+
+const BASE_58: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+// base58 treats the whole input as one big endian number, leading zero bytes are kept
+// as leading '1's
+pub fn base58_encode(hex: &[u8]) -> String {
+    let zeros = hex.iter().take_while(|&&b| b == 0).count();
+
+    // little endian base 58 digits, repeatedly multiplying by 256 and adding each byte
+    let mut digits: Vec<u8> = vec!();
+    for &byte in &hex[zeros..] {
+        let mut carry = u32::from(byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut s: String = (0..zeros).map(|_| '1').collect();
+    s.extend(digits.iter().rev().map(|&d| BASE_58[d as usize] as char));
+    s
+}
+
+pub fn base58_decode(string: &str) -> Result<Vec<u8>> {
+    let zeros = string.chars().take_while(|&c| c == '1').count();
+
+    // little endian bytes, the mirror image of base58_encode
+    let mut bytes: Vec<u8> = vec!();
+    for c in string.chars().skip(zeros) {
+        let mut carry = BASE_58.iter().position(|&x| x as char == c).ok_or(Error::InvalidBase58Char(c))? as u32;
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut v = vec![0; zeros];
+    v.extend(bytes.iter().rev());
+    Ok(v)
+}
+
+
+const Z85: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+// writes the five base 85 digits of a 32 bit word, most significant first
+fn base85_digits(mut word: u32) -> [u8; 5] {
+    let mut digits = [0u8; 5];
+    for digit in digits.iter_mut().rev() {
+        *digit = (word % 85) as u8;
+        word /= 85;
+    }
+    digits
+}
+
+fn base85_word(digits: &[u8]) -> Result<u32> {
+    let word = digits.iter().fold(0u64, |acc, &d| acc * 85 + u64::from(d));
+    if word > u64::from(u32::MAX) {
+        return Err(Error::Base85Overflow);
+    }
+    Ok(word as u32)
+}
+
+/// Adobe flavoured Ascii85 without the `<~ ~>` delimiters. Whole zero words become 'z' and
+/// a final partial word of n bytes is written as n + 1 characters.
+pub fn ascii85_encode(hex: &[u8]) -> String {
+    let mut s = String::with_capacity(hex.len() * 5 / 4 + 4);
+    for (word, chunk) in Bits::new(hex, 32).zip(hex.chunks(4)) {
+        if word == 0 && chunk.len() == 4 {
+            s.push('z');
+        } else {
+            let digits = base85_digits(word);
+            s.extend(digits[..chunk.len() + 1].iter().map(|&d| (d + b'!') as char));
+        }
+    }
+    s
+}
+
+// whitespace is skipped and the delimiters are optional
+pub fn ascii85_decode(string: &str) -> Result<Vec<u8>> {
+    let string = string.trim();
+    let string = string.strip_prefix("<~").unwrap_or(string);
+    let string = string.strip_suffix("~>").unwrap_or(string);
+
+    let mut v = Vec::with_capacity(string.len() * 4 / 5);
+    let mut group: Vec<u8> = Vec::with_capacity(5);
+    for c in string.bytes().filter(|&c| !is_wrap_space(c)) {
+        match c {
+            b'z' if group.is_empty() => v.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group.push(c - b'!');
+                if group.len() == 5 {
+                    v.extend_from_slice(&base85_word(&group)?.to_be_bytes());
+                    group.clear();
+                }
+            },
+            _ => return Err(Error::InvalidBase85Char(c as char)),
+        }
+    }
+
+    // a partial group is padded with the highest digit, 'u', then truncated again
+    match group.len() {
+        0 => (),
+        1 => return Err(Error::InvalidBase85Length),
+        len => {
+            group.resize(5, 84);
+            v.extend_from_slice(&base85_word(&group)?.to_be_bytes()[..len - 1]);
+        },
+    }
+    Ok(v)
+}
+
+/// ZeroMQ's Z85, which only encodes whole 4 byte words
+pub fn z85_encode(hex: &[u8]) -> Result<String> {
+    if !hex.len().is_multiple_of(4) {
+        return Err(Error::NotBlockAligned(hex.len()));
+    }
+    Ok(Bits::new(hex, 32).flat_map(|word| base85_digits(word).to_vec()).map(|d| Z85[d as usize] as char).collect())
+}
+
+pub fn z85_decode(string: &str) -> Result<Vec<u8>> {
+    if !string.len().is_multiple_of(5) {
+        return Err(Error::InvalidBase85Length);
+    }
+
+    let mut v = Vec::with_capacity(string.len() * 4 / 5);
+    for group in string.as_bytes().chunks(5) {
+        let mut digits = [0u8; 5];
+        for (digit, &c) in digits.iter_mut().zip(group) {
+            *digit = Z85.iter().position(|&x| x == c).ok_or(Error::InvalidBase85Char(c as char))? as u8;
+        }
+        v.extend_from_slice(&base85_word(&digits)?.to_be_bytes());
+    }
+    Ok(v)
+}
+
 
-    if text_len == 0 || last > 16 { return text.len() }
+// RFC 3986 unreserved characters, everything else gets escaped
+fn is_unreserved(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-' || c == b'.' || c == b'_' || c == b'~'
+}
+
+pub fn percent_encode(hex: &[u8]) -> String {
+    let mut s = String::with_capacity(hex.len());
+    for &c in hex {
+        if is_unreserved(c) {
+            s.push(c as char);
+        } else {
+            s.push_str(&format!("%{:02X}", c));
+        }
+    }
+    s
+}
 
-    for i in text_len - last as usize .. text_len {
-        if last != text[i] {
-            return text_len;
+// characters other than escapes are passed through untouched
+pub fn percent_decode(string: &str) -> Result<Vec<u8>> {
+    let bytes = string.as_bytes();
+    let mut v = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let escape = bytes.get(i + 1..i + 3).ok_or(Error::InvalidPercentEscape(i))?;
+            let h = char_to_hex(escape[0] as char).map_err(|_| Error::InvalidPercentEscape(i))?;
+            let l = char_to_hex(escape[1] as char).map_err(|_| Error::InvalidPercentEscape(i))?;
+            v.push((h << 4) | l);
+            i += 3;
+        } else {
+            v.push(bytes[i]);
+            i += 1;
         }
     }
-    text_len - last as usize
+    Ok(v)
+}
+
+
+// 0xff when a < b, 0x00 otherwise, without branching on either value
+fn ct_lt_mask(a: u8, b: u8) -> u8 {
+    (u16::from(a).wrapping_sub(u16::from(b)) >> 8) as u8
+}
+
+fn ct_eq_mask(a: u8, b: u8) -> u8 {
+    ct_lt_mask(a ^ b, 1)
+}
+
+// padded block sizes are 1 to 255 bytes, the length byte schemes can't count any further
+fn check_block_size(block_size: usize) -> result::Result<(), PaddingError> {
+    if block_size == 0 || block_size > 255 {
+        return Err(PaddingError);
+    }
+    Ok(())
+}
+
+// what the bytes in front of the final length byte have to be
+#[derive(Clone, Copy)]
+enum Filler {
+    // PKCS#7, every byte is the length
+    Length,
+    // ANSI X.923
+    Zero,
+    // ISO 10126, never checked
+    Random,
+}
+
+// the pad length if the final block ends in valid length byte padding. The whole last block is
+// examined whatever the pad byte claims, so the time taken says nothing about where it went wrong.
+fn length_byte_pad_len(text: &[u8], block_size: usize, filler: Filler) -> Option<usize> {
+    if block_size == 0 || block_size > 255 || text.is_empty() || !text.len().is_multiple_of(block_size) {
+        return None;
+    }
+
+    let last = text[text.len() - 1];
+    let (fill, check) = match filler {
+        Filler::Length => (last, 0xff),
+        Filler::Zero => (0, 0xff),
+        Filler::Random => (0, 0),
+    };
+
+    // the pad byte has to be between 1 and the block size
+    let mut bad = ct_lt_mask(last, 1) | ct_lt_mask(block_size as u8, last);
+    for (i, &b) in text.iter().rev().take(block_size).enumerate().skip(1) {
+        bad |= ct_lt_mask(i as u8, last) & check & (b ^ fill);
+    }
+
+    if bad == 0 { Some(last as usize) } else { None }
+}
+
+fn pad_with_length(text: &mut Vec<u8>, block_size: usize, filler: Filler) -> result::Result<(), PaddingError> {
+    check_block_size(block_size)?;
+    let remaining = block_size - text.len() % block_size;
+    match filler {
+        Filler::Length => text.extend(vec![remaining as u8; remaining]),
+        Filler::Zero => text.extend(vec![0; remaining - 1]),
+        Filler::Random => {
+            let mut rng = thread_rng();
+            text.extend(rng.gen_iter::<u8>().take(remaining - 1));
+        },
+    }
+    if let Filler::Zero | Filler::Random = filler {
+        text.push(remaining as u8);
+    }
+    Ok(())
+}
+
+pub fn pkcs7_validate(text: &[u8], block_size: usize) -> bool {
+    length_byte_pad_len(text, block_size, Filler::Length).is_some()
+}
+
+// strips the padding, text is left untouched if the padding is invalid
+pub fn unpad_pkcs7(text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+    Pkcs7.unpad(text, block_size)
+}
+
+// always pads, block aligned input gets a whole block of padding so unpadding is never ambiguous.
+// Block sizes are 1 to 255 bytes, the same ones unpad_pkcs7 takes.
+pub fn pad_pkcs7(text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+    pad_with_length(text, block_size, Filler::Length)
+}
+
+
+/// A block padding scheme. Every scheme but zero padding always adds at least one byte, so
+/// the padding can be told apart from the data.
+pub trait Padding {
+    /// pads text out to the block size, text is left untouched for block sizes outside 1 to 255
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError>;
+
+    /// the length of the padding at the end of text, None if it is malformed
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize>;
+
+    fn validate(&self, text: &[u8], block_size: usize) -> bool {
+        self.padding_len(text, block_size).is_some()
+    }
+
+    /// whether padding_len checks the bytes in front of the last one as well, which a padding
+    /// oracle attack needs to tell its guesses apart
+    fn checks_filler(&self) -> bool {
+        true
+    }
+
+    /// strips the padding, text is left untouched if the padding is invalid
+    fn unpad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        let pad_len = self.padding_len(text, block_size).ok_or(PaddingError)?;
+        let len = text.len() - pad_len;
+        text.truncate(len);
+        Ok(())
+    }
+}
+
+impl<P: Padding + ?Sized> Padding for &P {
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        (**self).pad(text, block_size)
+    }
+
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize> {
+        (**self).padding_len(text, block_size)
+    }
+
+    fn checks_filler(&self) -> bool {
+        (**self).checks_filler()
+    }
+}
+
+/// n bytes of value n
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pkcs7;
+
+/// zeros followed by the length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnsiX923;
+
+/// random bytes followed by the length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Iso10126;
+
+/// a single 0x80 followed by zeros
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Iso7816;
+
+/// zeros up to the block boundary, nothing if already aligned. Trailing zeros of the data
+/// are lost on unpadding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZeroPadding;
+
+impl Padding for Pkcs7 {
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        pad_with_length(text, block_size, Filler::Length)
+    }
+
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize> {
+        length_byte_pad_len(text, block_size, Filler::Length)
+    }
+}
+
+impl Padding for AnsiX923 {
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        pad_with_length(text, block_size, Filler::Zero)
+    }
+
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize> {
+        length_byte_pad_len(text, block_size, Filler::Zero)
+    }
+}
+
+impl Padding for Iso10126 {
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        pad_with_length(text, block_size, Filler::Random)
+    }
+
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize> {
+        length_byte_pad_len(text, block_size, Filler::Random)
+    }
+
+    // the filler is random, only the length byte can be checked
+    fn checks_filler(&self) -> bool {
+        false
+    }
 }
 
-pub fn pkcs7_validate(text: &[u8]) -> bool {
-    pkcs7_truncate_len(text) != text.len()
+impl Padding for Iso7816 {
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        check_block_size(block_size)?;
+        let remaining = block_size - text.len() % block_size;
+        text.push(0x80);
+        text.extend(vec![0; remaining - 1]);
+        Ok(())
+    }
+
+    // scans the whole last block for the 0x80 marker, again without branching on the data
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize> {
+        if block_size == 0 || block_size > 255 || text.is_empty() || !text.len().is_multiple_of(block_size) {
+            return None;
+        }
+
+        let (mut found, mut bad, mut pad_len) = (0u8, 0u8, 0u8);
+        for (i, &b) in text.iter().rev().take(block_size).enumerate() {
+            // the first non zero byte from the end, it has to be the marker
+            let here = !found & !ct_eq_mask(b, 0);
+            bad |= here & (b ^ 0x80);
+            pad_len |= here & (i + 1) as u8;
+            found |= here;
+        }
+
+        if bad | !found == 0 { Some(pad_len as usize) } else { None }
+    }
 }
 
-pub fn unpad_pkcs7(text: &mut Vec<u8>) {
-    let truncate_len = pkcs7_truncate_len(&text);
-    text.truncate(truncate_len);
+impl Padding for ZeroPadding {
+    fn pad(&self, text: &mut Vec<u8>, block_size: usize) -> result::Result<(), PaddingError> {
+        check_block_size(block_size)?;
+        let remaining = (block_size - text.len() % block_size) % block_size;
+        text.extend(vec![0; remaining]);
+        Ok(())
+    }
+
+    // counts the trailing zeros across the whole last block rather than stopping at the
+    // first data byte
+    fn padding_len(&self, text: &[u8], block_size: usize) -> Option<usize> {
+        if block_size == 0 || block_size > 255 || !text.len().is_multiple_of(block_size) {
+            return None;
+        }
+
+        let (mut zeros, mut pad_len) = (0xffu8, 0);
+        for &b in text.iter().rev().take(block_size) {
+            zeros &= ct_eq_mask(b, 0);
+            pad_len += (zeros & 1) as usize;
+        }
+        Some(pad_len)
+    }
 }
 
-pub fn pad_pkcs7(text: &mut Vec<u8>, key_size: usize) {
-    let mod_len = text.len() % key_size;
-    let remaining = key_size - mod_len;
 
-    if remaining > 0 && remaining != key_size{
-         let pad = vec![remaining as u8; remaining];
-         text.extend(pad);
-    } 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_hex() {
+        assert_eq!(string_to_hex("49276d").unwrap(), b"I'm");
+        match string_to_hex("4927x6") {
+            Err(Error::InvalidHexChar('x')) => (),
+            other => panic!("expected InvalidHexChar, got {:?}", other),
+        }
+        match string_to_hex("49276") {
+            Err(Error::OddHexLength) => (),
+            other => panic!("expected OddHexLength, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_base64() {
+        assert_eq!(base64_to_hex("SSdt").unwrap(), b"I'm");
+        match base64_to_hex("SSd") {
+            Err(Error::InvalidBase64Length) => (),
+            other => panic!("expected InvalidBase64Length, got {:?}", other),
+        }
+        match base64_to_hex("S!dt") {
+            Err(Error::InvalidBase64Char('!')) => (),
+            other => panic!("expected InvalidBase64Char, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hex_case_and_whitespace() {
+        assert_eq!(string_to_hex("DEADbeef").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert!(string_to_hex("dead beef").is_err());
+        assert_eq!(string_to_hex_lenient("de ad\r\nBE EF\n").unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert!(string_to_hex_lenient("dead bee").is_err());
+    }
+
+    #[test]
+    fn base64_flavours() {
+        let bytes = [0xfb, 0xff, 0xbf, 0x3e];
+        assert_eq!(Base64::STANDARD.encode(&bytes), "+/+/Pg==");
+        assert_eq!(Base64::URL_SAFE.encode(&bytes), "-_-_Pg==");
+        assert_eq!(Base64::URL_SAFE_NO_PAD.encode(&bytes), "-_-_Pg");
+
+        assert_eq!(Base64::URL_SAFE_NO_PAD.decode("-_-_Pg").unwrap(), bytes);
+        assert!(Base64::URL_SAFE_NO_PAD.decode("-_-_Pg==").is_err());
+        assert!(Base64::URL_SAFE.decode("-_-_Pg").is_err());
+        assert_eq!(Base64::URL_SAFE.lenient().decode("-_-_Pg").unwrap(), bytes);
+        assert!(Base64::STANDARD.decode("-_-_Pg==").is_err());
+
+        // nothing may follow the padding
+        assert!(Base64::STANDARD.decode("Pg==Pg==").is_err());
+        assert!(Base64::STANDARD.decode("P===").is_err());
+    }
+
+    #[test]
+    fn base64_mime_lines() {
+        let wrapped = "SSdtIGJhY2sgYW5kIEknbSBy\r\naW5naW4nIHRoZSBi\nZWxs\n";
+        assert!(Base64::STANDARD.decode(wrapped).is_err());
+        assert_eq!(Base64::MIME.decode(wrapped).unwrap(), b"I'm back and I'm ringin' the bell");
+        assert!(Base64::MIME.decode("SSd0\n*").is_err());
+    }
+
+    #[test]
+    fn decode_reader() {
+        let encoded = include_str!("challenges/data/7.txt");
+        let mut decoded = vec!();
+        Base64::MIME.reader(encoded.as_bytes()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, Base64::MIME.decode(encoded).unwrap());
+
+        let mut reader = DecodeReader::hex("49 27\n6d".as_bytes(), true);
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, *b"I");
+        let mut rest = vec!();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"'m");
+
+        let err = Base64::STANDARD.reader("SSd".as_bytes()).read_to_end(&mut vec!()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn wide_bits() {
+        let words: Vec<u32> = Bits::new(&[0xde, 0xad, 0xbe, 0xef, 0x01], 32).collect();
+        assert_eq!(words, [0xdeadbeef, 0x01000000]);
+        let fields: Vec<u32> = Bits::new(&[0xff, 0xf0, 0x0f], 12).collect();
+        assert_eq!(fields, [0xfff, 0x00f]);
+    }
+
+    #[test]
+    fn base32_vectors() {
+        let vectors = [("", ""), ("f", "MY======"), ("fo", "MZXQ===="), ("foo", "MZXW6==="),
+            ("foob", "MZXW6YQ="), ("fooba", "MZXW6YTB"), ("foobar", "MZXW6YTBOI======")];
+        for &(plain, encoded) in vectors.iter() {
+            assert_eq!(Base32::STANDARD.encode(plain.as_bytes()), encoded);
+            assert_eq!(Base32::STANDARD.decode(encoded).unwrap(), plain.as_bytes());
+        }
+        assert_eq!(Base32::HEX.encode(b"foobar"), "CPNMUOJ1E8======");
+        assert_eq!(Base32::STANDARD.no_pad().encode(b"foob"), "MZXW6YQ");
+        assert_eq!(Base32::STANDARD.no_pad().decode("MZXW6YQ").unwrap(), b"foob");
+
+        assert!(Base32::STANDARD.decode("MZXW6YQ").is_err());
+        assert!(Base32::STANDARD.decode("MZXW6Y==").is_err());
+        assert!(Base32::STANDARD.decode("mzxw6yq=").is_err());
+
+        // over padded, under padded and padding with nothing in front of it
+        for bad in &["MY=======", "MY=====", "MZXW6YQ==", "MZXW6====", "========", "MZXW6YTB========",
+                "MZXW6YTBOI==============="] {
+            assert!(Base32::STANDARD.decode(bad).is_err());
+            assert!(Base32::STANDARD.no_pad().decode(bad).is_err());
+        }
+        assert_eq!(Base32::STANDARD.no_pad().decode("MZXW6YQ=").unwrap(), b"foob");
+    }
+
+    #[test]
+    fn base58_vectors() {
+        assert_eq!(base58_encode(b"Hello World!"), "2NEpo7TZRRrLZSi2U");
+        let address = string_to_hex("00eb15231dfceb60925886b67d065299925915aeb172c06647").unwrap();
+        assert_eq!(base58_encode(&address), "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L");
+        assert_eq!(base58_decode("1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L").unwrap(), address);
+        assert_eq!(base58_encode(&[0, 0]), "11");
+        assert!(base58_decode("0OIl").is_err());
+    }
+
+    #[test]
+    fn base85_vectors() {
+        assert_eq!(ascii85_encode(b"Man "), "9jqo^");
+        assert_eq!(ascii85_encode(b"sure."), "F*2M7/c");
+        assert_eq!(ascii85_encode(&[0, 0, 0, 0, 0, 0]), "z!!!");
+        assert_eq!(ascii85_decode("<~F*2M7\n/c~>").unwrap(), b"sure.");
+        assert_eq!(ascii85_decode("z!!!").unwrap(), [0; 6]);
+        assert!(ascii85_decode("F*2M7/").is_err());
+        assert!(ascii85_decode("s8W-\"").is_err());
+        assert!(ascii85_decode("9jqvo").is_err());
+
+        let bytes = [0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b];
+        assert_eq!(z85_encode(&bytes).unwrap(), "HelloWorld");
+        assert_eq!(z85_decode("HelloWorld").unwrap(), bytes);
+        assert!(z85_encode(&bytes[..5]).is_err());
+        assert!(z85_decode("Hello").is_ok() && z85_decode("Hell").is_err());
+    }
+
+    #[test]
+    fn percent_vectors() {
+        assert_eq!(percent_encode("a b&c/~é".as_bytes()), "a%20b%26c%2F~%C3%A9");
+        assert_eq!(percent_decode("a%20b%26c%2f~%C3%A9").unwrap(), "a b&c/~é".as_bytes());
+        match percent_decode("100%") {
+            Err(Error::InvalidPercentEscape(3)) => (),
+            other => panic!("expected InvalidPercentEscape, got {:?}", other),
+        }
+        assert!(percent_decode("%g0").is_err());
+    }
+
+    #[test]
+    fn pkcs7_always_pads() {
+        let mut text = b"YELLOW SUBMARINE".to_vec();
+        pad_pkcs7(&mut text, 20).unwrap();
+        assert_eq!(text, b"YELLOW SUBMARINE\x04\x04\x04\x04");
+
+        let mut aligned = b"YELLOW SUBMARINE".to_vec();
+        pad_pkcs7(&mut aligned, 16).unwrap();
+        assert_eq!(aligned.len(), 32);
+        assert_eq!(aligned[16..], [16; 16]);
+        unpad_pkcs7(&mut aligned, 16).unwrap();
+        assert_eq!(aligned, b"YELLOW SUBMARINE");
+
+        for &block_size in &[1, 3, 8, 255] {
+            let mut text = vec![0; 300];
+            pad_pkcs7(&mut text, block_size).unwrap();
+            assert!(text.len().is_multiple_of(block_size) && text.len() > 300);
+            unpad_pkcs7(&mut text, block_size).unwrap();
+            assert_eq!(text, vec![0; 300]);
+        }
+
+        // the same block sizes unpadding refuses, and text is left alone
+        for &block_size in &[0, 256] {
+            let mut text = vec![0; 30];
+            assert_eq!(pad_pkcs7(&mut text, block_size), Err(PaddingError));
+            assert_eq!(text, vec![0; 30]);
+        }
+    }
+
+    #[test]
+    fn pkcs7_rejects() {
+        assert!(pkcs7_validate(b"ICE ICE BABY\x04\x04\x04\x04", 16));
+        assert!(!pkcs7_validate(b"ICE ICE BABY\x05\x05\x05\x05", 16));
+        assert!(!pkcs7_validate(b"ICE ICE BABY\x01\x02\x03\x04", 16));
+        assert!(!pkcs7_validate(b"ICE ICE BABY123\x00", 16));
+        assert!(!pkcs7_validate(b"ICE ICE BABY\x04\x04\x04", 16));
+        assert!(!pkcs7_validate(b"", 16));
+        // a pad byte larger than the block
+        assert!(!pkcs7_validate(&[17; 32], 16));
+        assert!(pkcs7_validate(&[17; 34], 17));
+
+        let mut text = b"ICE ICE BABY\x05\x05\x05\x05".to_vec();
+        assert_eq!(unpad_pkcs7(&mut text, 16), Err(PaddingError));
+        assert_eq!(text.len(), 16);
+    }
+
+    #[test]
+    fn padding_schemes() {
+        let text = b"ICE ICE BABY".to_vec();
+        let expected: [(&dyn Padding, &[u8]); 4] = [
+            (&Pkcs7, b"ICE ICE BABY\x04\x04\x04\x04"),
+            (&AnsiX923, b"ICE ICE BABY\x00\x00\x00\x04"),
+            (&Iso7816, b"ICE ICE BABY\x80\x00\x00\x00"),
+            (&ZeroPadding, b"ICE ICE BABY\x00\x00\x00\x00"),
+        ];
+        for &(padding, padded) in expected.iter() {
+            let mut v = text.clone();
+            padding.pad(&mut v, 16).unwrap();
+            assert_eq!(v, padded);
+            padding.unpad(&mut v, 16).unwrap();
+            assert_eq!(v, text);
+        }
+
+        let mut v = text.clone();
+        Iso10126.pad(&mut v, 16).unwrap();
+        assert_eq!((v.len(), v[15]), (16, 4));
+        Iso10126.unpad(&mut v, 16).unwrap();
+        assert_eq!(v, text);
+
+        // whole blocks of padding for aligned input, except for zero padding
+        let schemes: [&dyn Padding; 4] = [&Pkcs7, &AnsiX923, &Iso10126, &Iso7816];
+        for padding in schemes.iter() {
+            let mut v = vec![1; 16];
+            padding.pad(&mut v, 16).unwrap();
+            assert_eq!(v.len(), 32);
+        }
+        let mut v = vec![1; 16];
+        ZeroPadding.pad(&mut v, 16).unwrap();
+        assert_eq!(v.len(), 16);
+    }
+
+    #[test]
+    fn padding_rejects() {
+        assert!(!AnsiX923.validate(b"ICE ICE BABY\x00\x01\x00\x04", 16));
+        assert!(Iso10126.validate(b"ICE ICE BABY\x00\x01\x00\x04", 16));
+        assert!(!Iso10126.validate(b"ICE ICE BABY\x00\x01\x00\x11", 16));
+        assert!(Iso7816.validate(b"ICE ICE BABY\x80\x00\x00\x00", 16));
+        assert!(Iso7816.validate(b"ICE ICE BABY123\x80", 16));
+        assert!(!Iso7816.validate(b"ICE ICE BABY\x81\x00\x00\x00", 16));
+        assert!(!Iso7816.validate(&[0; 16], 16));
+        assert!(!ZeroPadding.validate(&[0; 15], 16));
+        // zeros in the data in front of the padding aren't counted
+        assert_eq!(ZeroPadding.padding_len(b"ICE\x00ICE BABY!\x00\x00\x00", 16), Some(3));
+        assert_eq!(ZeroPadding.padding_len(&[0; 32], 16), Some(16));
+
+        // every scheme refuses the same block sizes both ways
+        let schemes: [&dyn Padding; 5] = [&Pkcs7, &AnsiX923, &Iso10126, &Iso7816, &ZeroPadding];
+        for padding in schemes.iter() {
+            for &block_size in &[0, 256] {
+                let mut v = vec![1; 10];
+                assert_eq!(padding.pad(&mut v, block_size), Err(PaddingError));
+                assert_eq!(v, vec![1; 10]);
+                assert!(!padding.validate(&[0; 512], block_size));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        use crypter::random_bytes;
+
+        for len in 0..40 {
+            let bytes = random_bytes(len, len);
+            assert_eq!(Base64::URL_SAFE_NO_PAD.decode(&Base64::URL_SAFE_NO_PAD.encode(&bytes)).unwrap(), bytes);
+            assert_eq!(Base32::STANDARD.decode(&Base32::STANDARD.encode(&bytes)).unwrap(), bytes);
+            assert_eq!(Base32::HEX.no_pad().decode(&Base32::HEX.no_pad().encode(&bytes)).unwrap(), bytes);
+            assert_eq!(base58_decode(&base58_encode(&bytes)).unwrap(), bytes);
+            assert_eq!(ascii85_decode(&ascii85_encode(&bytes)).unwrap(), bytes);
+            assert_eq!(percent_decode(&percent_encode(&bytes)).unwrap(), bytes);
+
+            let aligned = &bytes[..len - len % 4];
+            assert_eq!(z85_decode(&z85_encode(aligned).unwrap()).unwrap(), aligned);
+        }
+    }
 }
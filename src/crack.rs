@@ -1,59 +1,207 @@
-pub fn find_xor_key(m: &[u8]) -> u8 {
+use text::CharFreq;
+
+/// How far bytes are from english text, lower is better. Control characters and bytes past
+/// ascii count heavily against it instead of ruling it out, so a stray one doesn't hide the
+/// right key.
+pub fn text_score(en: &CharFreq, bytes: &[u8]) -> f32 {
+    if bytes.is_empty() {
+        return f32::MAX;
+    }
+    let text: String = bytes.iter().map(|&b| b as char).collect();
+    let unprintable = bytes.iter().filter(|&&b| !printable(b)).count();
+    en.dist_from_string(&text) + 4.0 * unprintable as f32 / bytes.len() as f32
+}
+
+/// Every single byte xor key with the text_score of what it decrypts m to, best first
+pub fn find_xor_key(m: &[u8]) -> Vec<(u8, f32)> {
 	use combine::xor_byte;
-	use std::f32;
-	use text::CharFreq;
 
 	let en = CharFreq::for_english();
+	let mut ranked: Vec<(u8, f32)> = (0..=255).map(|b| (b, text_score(&en, &xor_byte(m, b)))).collect();
+	ranked.sort_by(|a, b| a.1.total_cmp(&b.1));
+	ranked
+}
+
+/// How well key_size fits a repeating xor cipher text by three measures, each scaled to 0..1
+/// over the sizes tried, and their weighted sum. Higher is better in all but hamming.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySize {
+    pub size: usize,
+    /// edit distance between blocks of size bytes, per bit, lower is better
+    pub hamming: f32,
+    /// index of coincidence of the columns
+    pub coincidence: f32,
+    /// how many repeats of a few bytes sit a multiple of size apart
+    pub kasiski: f32,
+    /// the weighted sum, or that of a multiple of size, see rank_key_sizes
+    pub score: f32,
+}
+
+// blocks compared pairwise by hamming_distance, 8 makes 28 pairs
+const HAMMING_BLOCKS: usize = 8;
+// length of the repeats kasiski looks for
+const KASISKI_LEN: usize = 3;
+// sizes guess_key_size returns
+const KEY_SIZES_GUESSED: usize = 3;
+
+// average edit distance over every pair of the first few blocks, per bit. Plain text under
+// the same key bytes differs in fewer bits than random bytes.
+fn hamming_distance(cipher: &[u8], key_size: usize) -> f32 {
+    use measure::hamming;
+
+    let blocks: Vec<&[u8]> = cipher.chunks_exact(key_size).take(HAMMING_BLOCKS).collect();
+    let mut total = 0;
+    let mut pairs = 0;
+    for (i, a) in blocks.iter().enumerate() {
+        for b in &blocks[i + 1..] {
+            total += hamming(a, b);
+            pairs += 1;
+        }
+    }
+    // a single block, nothing to compare it to
+    if pairs == 0 {
+        return 1.0;
+    }
+    total as f32 / pairs as f32 / (8 * key_size) as f32
+}
+
+// chance two bytes of the same column are equal, averaged over the columns. Each column is
+// text xored with one byte, which keeps its letter frequencies, about 0.06 for english
+// against 1/256 for noise.
+fn index_of_coincidence(cipher: &[u8], key_size: usize) -> f32 {
+    // u64 all the way, a column of more than 2^16 bytes overflows the products in u32
+    let mut counts = vec![[0u64; 256]; key_size];
+    for (i, &c) in cipher.iter().enumerate() {
+        counts[i % key_size][c as usize] += 1;
+    }
+
+    let mut total = 0.0;
+    let mut columns = 0;
+    for column in counts.iter() {
+        let n: u64 = column.iter().sum();
+        if n < 2 {
+            continue;
+        }
+        let same: u64 = column.iter().map(|&c| c * c.saturating_sub(1)).sum();
+        total += (same as f64 / (n * (n - 1)) as f64) as f32;
+        columns += 1;
+    }
+    if columns == 0 { 0.0 } else { total / columns as f32 }
+}
+
+// distances between repeats of the same few bytes. The same plain text under the same
+// stretch of key repeats in the cipher text too, a multiple of the key size apart.
+fn kasiski_distances(cipher: &[u8]) -> Vec<usize> {
+    use std::collections::HashMap;
 
-	// xor, distance from en, string
-	let mut best = (0x0, f32::MAX);
-
-	for b in 0x00..0xff {
-	    let s = String::from_utf8(xor_byte(m, b)).unwrap_or("".to_string());
-	    if s.len() > 0 {
-	        let mut c = CharFreq::new();
-	        c.count_all(&s);
-
-	        let d = c.dist(&en);
-	        if d < best.1 {
-	            best = (b, d);
-	        }
-	    }
-	}
-	best.0
-}
-
-pub fn guess_key_size(cipher: &[u8]) -> Vec<usize>{
-	use measure::hamming;
-	let mut best = (vec!(8.0 as f32), vec!(cipher.len() as usize));
-	let threshold = 0.10;
-
-	for ks in 2..40 {
-	    let mut chunks = cipher.chunks(ks);
-	    let mut total_diff = 0.0;
-	    let mut pairs = 0;
-	    while let (Some(a), Some(b)) = (chunks.next(), chunks.next()) {
-	        pairs += 1;
-	        total_diff += hamming(a, b) as f32;
-	    }
-
-	    let diff = (total_diff / pairs as f32) / ks as f32;
-	    let best_avg = best.0.iter().fold(0.0, |acc, &x| acc + x) / best.0.len() as f32;
-	    let per_diff = ((best_avg - diff) / best_avg).abs();
-
-	    // if the percentage diff is less than 0.1
-	    if per_diff < threshold {
-	    	best.0.push(diff);
-	    	best.1.push(ks);
-	    // if the best average is greater than diff, (diff is good)
-	    } else if diff < best_avg{
-	    	best = (vec!(diff), vec!(ks));
-	    }
-	}
-	best.1
+    let mut last_seen: HashMap<&[u8], usize> = HashMap::new();
+    let mut distances = vec!();
+    for (at, window) in cipher.windows(KASISKI_LEN).enumerate() {
+        if let Some(before) = last_seen.insert(window, at) {
+            distances.push(at - before);
+        }
+    }
+    distances
+}
+
+// the share of distances key_size divides, against the share it would divide by chance
+fn kasiski_score(distances: &[usize], key_size: usize) -> f32 {
+    if distances.is_empty() {
+        return 0.0;
+    }
+    let divided = distances.iter().filter(|&&d| d % key_size == 0).count();
+    divided as f32 / distances.len() as f32 * key_size as f32
+}
+
+// values scaled to 0..1, all 0 when they're all the same
+fn scaled(values: &[f32]) -> Vec<f32> {
+    let min = values.iter().cloned().fold(f32::MAX, f32::min);
+    let max = values.iter().cloned().fold(f32::MIN, f32::max);
+    values.iter().map(|&v| if max > min { (v - min) / (max - min) } else { 0.0 }).collect()
+}
+
+/// Every key size from 2 up to max_size (and half the cipher text) scored, best first.
+/// Multiples of the key size fit as well as the size itself or better, so a size takes the
+/// score of any multiple scoring less than twice as high and goes first on the tie.
+pub fn rank_key_sizes(cipher: &[u8], max_size: usize) -> Vec<KeySize> {
+    let sizes: Vec<usize> = (2..=max_size.min(cipher.len() / 2)).collect();
+    let distances = kasiski_distances(cipher);
+
+    let hamming: Vec<f32> = sizes.iter().map(|&ks| hamming_distance(cipher, ks)).collect();
+    let coincidence: Vec<f32> = sizes.iter().map(|&ks| index_of_coincidence(cipher, ks)).collect();
+    let kasiski: Vec<f32> = sizes.iter().map(|&ks| kasiski_score(&distances, ks)).collect();
+    let (h, c, k) = (scaled(&hamming), scaled(&coincidence), scaled(&kasiski));
+
+    // kasiski has little to go on in short cipher texts, so it counts for less
+    let scores: Vec<f32> = (0..sizes.len()).map(|i| (1.0 - h[i]) + c[i] + 0.5 * k[i]).collect();
+
+    let mut ranked: Vec<KeySize> = sizes.iter().enumerate().map(|(i, &size)| {
+        // a multiple that only beats size on repeats in the plain text counts for size
+        let score = sizes.iter()
+            .zip(scores.iter())
+            .filter(|&(&m, &score)| m % size == 0 && 2.0 * scores[i] >= score)
+            .map(|(_, &score)| score)
+            .fold(scores[i], f32::max);
+        KeySize { size, hamming: hamming[i], coincidence: coincidence[i], kasiski: kasiski[i], score }
+    }).collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.size.cmp(&b.size)));
+    ranked
+}
+
+/// The best few key sizes from 2 up to 39 bytes, best first. See rank_key_sizes for the
+/// scores behind them.
+pub fn guess_key_size(cipher: &[u8]) -> Vec<usize> {
+    rank_key_sizes(cipher, 39).iter().take(KEY_SIZES_GUESSED).map(|ks| ks.size).collect()
+}
+
+/// Breaks a repeating key xor by finding the key for each of the tries best key sizes up to
+/// max_size and keeping the one whose plain text reads most like english. Returns the key
+/// and the plain text. Multiples of a size already tried are skipped, a longer key always
+/// fits a little closer without being any more right.
+pub fn break_repeating_key_xor(cipher: &[u8], max_size: usize, tries: usize) -> (Vec<u8>, Vec<u8>) {
+    use combine::xor_each;
+
+    let en = CharFreq::for_english();
+    let mut sizes: Vec<usize> = vec!();
+    for ks in rank_key_sizes(cipher, max_size) {
+        if sizes.len() < tries && sizes.iter().all(|&tried| ks.size % tried != 0) {
+            sizes.push(ks.size);
+        }
+    }
+
+    let mut best: Option<(f32, Vec<u8>, Vec<u8>)> = None;
+    for key_size in sizes {
+        let key = find_repeated_xor_key(cipher, key_size);
+        let plain_text = xor_each(cipher, &key);
+        let score = text_score(&en, &plain_text);
+        if best.as_ref().is_none_or(|b| score < b.0) {
+            best = Some((score, key, plain_text));
+        }
+    }
+    best.map(|(_, key, plain_text)| (key, plain_text)).unwrap_or_else(|| (vec!(), cipher.to_vec()))
 }
 
+// keys kept after every column, and the candidates tried for each column
+const BEAM_WIDTH: usize = 8;
+const BEAM_CANDIDATES: usize = 3;
+
+// text_score of the columns a partial key covers
+fn partial_score(en: &CharFreq, cipher: &[u8], key_size: usize, key: &[u8]) -> f32 {
+    let decrypted: Vec<u8> = cipher.iter()
+        .enumerate()
+        .filter(|&(i, _)| i % key_size < key.len())
+        .map(|(i, &c)| c ^ key[i % key_size])
+        .collect();
+    text_score(en, &decrypted)
+}
+
+/// The repeating xor key of key_size bytes. Each column's best few single byte keys are
+/// beam searched on how the columns read together, a letter count over a single column
+/// can't tell a few close calls apart. A key_size of 0 gives back an empty key.
 pub fn find_repeated_xor_key(cipher: &[u8], key_size: usize) -> Vec<u8> {
+    if key_size == 0 {
+        return vec!();
+    }
     let mut blocks: Vec<Vec<u8>> = vec!();
     for chunk in cipher.chunks(key_size) {
     	for (idx, block) in chunk.iter().enumerate() {
@@ -65,5 +213,175 @@ pub fn find_repeated_xor_key(cipher: &[u8], key_size: usize) -> Vec<u8> {
     	}
     }
 
-    blocks.iter().map(|v| find_xor_key(v)).collect()
+    let en = CharFreq::for_english();
+    let mut beam: Vec<(Vec<u8>, f32)> = vec![(vec!(), 0.0)];
+    for ranked in blocks.iter().map(|v| find_xor_key(v)) {
+        let mut next: Vec<(Vec<u8>, f32)> = vec!();
+        for (key, _) in beam.iter() {
+            for &(b, _) in ranked.iter().take(BEAM_CANDIDATES) {
+                let mut key = key.clone();
+                key.push(b);
+                let score = partial_score(&en, cipher, key_size, &key);
+                next.push((key, score));
+            }
+        }
+        next.sort_by(|a, b| a.1.total_cmp(&b.1));
+        next.truncate(BEAM_WIDTH);
+        beam = next;
+    }
+    beam.swap_remove(0).0
+}
+
+// printable ascii and line breaks and tabs, anything else is unlikely in text
+fn printable(b: u8) -> bool {
+    (0x20..0x7f).contains(&b) || b == b'\n' || b == b'\r' || b == b'\t'
+}
+
+// how likely cur is right after prev in english, higher is better. Letter frequencies and a
+// few rules about which characters follow which.
+fn pair_score(en: &CharFreq, prev: Option<u8>, cur: u8) -> f32 {
+    if !printable(cur) {
+        return -20.0;
+    }
+    let c = cur as char;
+    let mut score = en.probability(c).max(0.001).ln();
+
+    match prev.map(|p| p as char) {
+        // text starts with a capital
+        None => if c.is_ascii_uppercase() {
+            score += 1.0;
+        },
+        Some(prev) => {
+            if prev.is_ascii_lowercase() && c.is_ascii_uppercase() {
+                score -= 4.0;
+            }
+            if prev == ' ' && c == ' ' {
+                score -= 4.0;
+            }
+            if ",.;:!?".contains(prev) && c.is_ascii_alphanumeric() {
+                score -= 3.0;
+            }
+            if prev.is_ascii_alphabetic() && c.is_ascii_digit() || prev.is_ascii_digit() && c.is_ascii_alphabetic() {
+                score -= 3.0;
+            }
+        }
+    }
+    score
+}
+
+// columns with fewer bytes than this are too short for a frequency count
+const TAIL_ROWS: usize = 10;
+
+// how well key fits column idx of the rows that reach it, higher is better. Long columns are
+// compared to english as a whole, with the pair scores only to break ties like the case
+// flip of xoring 0x20. Short ones go by the pairs alone, against the plain text on both
+// sides when there is any.
+fn column_score(en: &CharFreq, cipher_texts: &[Vec<u8>], plain_texts: &[Vec<u8>], rows: &[usize], idx: usize, key: u8) -> f32 {
+    let pairs: f32 = rows.iter().map(|&row| {
+        let cur = cipher_texts[row][idx] ^ key;
+        let prev = if idx > 0 { Some(plain_texts[row][idx - 1]) } else { None };
+        let next = plain_texts[row].get(idx + 1).map(|&n| pair_score(en, Some(cur), n)).unwrap_or(0.0);
+        pair_score(en, prev, cur) + next
+    }).sum();
+
+    if rows.len() < TAIL_ROWS {
+        return pairs;
+    }
+    let decrypted: Vec<u8> = rows.iter().map(|&row| cipher_texts[row][idx] ^ key).collect();
+    0.5 * pairs / rows.len() as f32 - text_score(en, &decrypted)
+}
+
+/// Breaks CTR cipher texts encrypted under the same key and nonce, which is a repeated key
+/// xor in disguise. Every column of bytes at the same offset shares a key stream byte,
+/// picked by comparing the column to english. The tail, where only the longest few cipher
+/// texts still reach, is too short to count, so each byte there is scored by how well it
+/// fits the plain text around it, once going forward and again once the rest is known.
+pub fn break_fixed_nonce_ctr(cipher_texts: &[Vec<u8>]) -> (Vec<u8>, Vec<Vec<u8>>) {
+    let en = CharFreq::for_english();
+    let longest = cipher_texts.iter().map(|c| c.len()).max().unwrap_or(0);
+    let mut plain_texts: Vec<Vec<u8>> = cipher_texts.iter().map(|c| Vec::with_capacity(c.len())).collect();
+    let rows: Vec<Vec<usize>> = (0..longest)
+        .map(|idx| (0..cipher_texts.len()).filter(|&row| cipher_texts[row].len() > idx).collect())
+        .collect();
+
+    let mut key_stream = vec![0; longest];
+    let tail = rows.iter().position(|r| r.len() < TAIL_ROWS).unwrap_or(longest);
+    for pass in 0..2 {
+        let columns = if pass == 0 { 0..longest } else { tail..longest };
+        for idx in columns {
+            let key = (0..=255u8).max_by(|&a, &b| {
+                column_score(&en, cipher_texts, &plain_texts, &rows[idx], idx, a)
+                    .total_cmp(&column_score(&en, cipher_texts, &plain_texts, &rows[idx], idx, b))
+            }).unwrap();
+
+            key_stream[idx] = key;
+            for &row in &rows[idx] {
+                let byte = cipher_texts[row][idx] ^ key;
+                match plain_texts[row].get_mut(idx) {
+                    Some(plain) => *plain = byte,
+                    None => plain_texts[row].push(byte),
+                }
+            }
+        }
+    }
+    (key_stream, plain_texts)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use combine::{xor_byte, xor_each};
+
+    #[test]
+    fn ranks_every_key() {
+        let plain_text = b"Cooking MC's like a pound of bacon\x01";
+        let ranked = find_xor_key(&xor_byte(plain_text, 0xff));
+        assert_eq!(ranked.len(), 256);
+        assert_eq!(ranked[0].0, 0xff);
+        assert!(ranked.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        // bytes past ascii used to rule a key out altogether
+        let ranked = find_xor_key(&xor_byte(b"na\xefve caf\xe9 with a lot of english around it", 0x42));
+        assert_eq!(ranked[0].0, 0x42);
+    }
+
+    const LYRICS: &[u8] = b"Burning 'em, if you ain't quick and nimble\nI go crazy when I hear a cymbal\n\
+        And a high hat with a souped up tempo\nI'm on a roll, it's time to go solo\n\
+        Rollin' in my five point oh\nWith my rag-top down so my hair can blow\n\
+        The girlies on standby, waving just to say hi\nDid you stop? No, I just drove by\n\
+        Kept on pursuing to the next stop\nI busted a left and I'm heading to the next block";
+
+    #[test]
+    fn repeated_key() {
+        assert_eq!(find_repeated_xor_key(&xor_each(LYRICS, b"ICE"), 3), b"ICE");
+        assert!(find_repeated_xor_key(&xor_each(LYRICS, b"ICE"), 0).is_empty());
+    }
+
+    #[test]
+    fn key_sizes() {
+        let cipher = xor_each(LYRICS, b"lantern");
+        let ranked = rank_key_sizes(&cipher, 40);
+        assert_eq!(ranked.len(), 39);
+        assert_eq!(ranked[0].size, 7);
+        assert!(ranked.windows(2).all(|w| w[0].score >= w[1].score));
+        // each measure on its own already sets 7 apart from its neighbours
+        let seven = &ranked[0];
+        let six = ranked.iter().find(|ks| ks.size == 6).unwrap();
+        assert!(seven.hamming < six.hamming && seven.coincidence > six.coincidence && seven.kasiski > six.kasiski);
+
+        assert_eq!(guess_key_size(&cipher)[0], 7);
+        assert!(rank_key_sizes(b"abc", 40).is_empty());
+
+        // long columns, their pair counts don't fit in 32 bits
+        let coincidence = index_of_coincidence(&vec![b'a'; 1 << 18], 2);
+        assert!((coincidence - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn break_repeating() {
+        let (key, plain_text) = break_repeating_key_xor(&xor_each(LYRICS, b"Vanilla"), 40, 3);
+        assert_eq!(key, b"Vanilla");
+        assert_eq!(plain_text, LYRICS);
+    }
 }
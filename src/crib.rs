@@ -0,0 +1,218 @@
+// Crib dragging for many-time pads, cipher texts sharing a key stream that are too short or
+// too few for the column statistics in crack. Xoring two of them cancels the key stream and
+// leaves the xor of their plain texts, so guessing a word in one reveals the other.
+
+use combine::xor_each_no_wrap;
+use crack::text_score;
+use error::{Error, Result};
+use text::CharFreq;
+
+
+/// What a crib placed at offset turns the other side into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub offset: usize,
+    pub text: Vec<u8>,
+    /// distance from english, lower is better
+    pub score: f32,
+}
+
+/// Slides crib across the xor of two cipher texts. At every offset where the crib fits, the
+/// fragment is what the other message says there if one of them holds the crib. Best first.
+pub fn drag(a: &[u8], b: &[u8], crib: &[u8]) -> Vec<Fragment> {
+    let en = CharFreq::for_english();
+    let xored = xor_each_no_wrap(a, b);
+    if crib.is_empty() || crib.len() > xored.len() {
+        return vec!();
+    }
+
+    let mut fragments: Vec<Fragment> = xored.windows(crib.len())
+        .enumerate()
+        .map(|(offset, window)| {
+            let text = xor_each_no_wrap(window, crib);
+            let score = text_score(&en, &text);
+            Fragment { offset, text, score }
+        })
+        .collect();
+    fragments.sort_by(|x, y| x.score.total_cmp(&y.score));
+    fragments
+}
+
+/// A crib placed in one cipher text, scored on what it does to all the others
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub offset: usize,
+    /// the other cipher texts at offset, cut short where they end, in order and without the
+    /// one holding the crib
+    pub others: Vec<Vec<u8>>,
+    pub score: f32,
+}
+
+/// A crib dragging session over cipher texts that share a key stream. Key stream bytes get
+/// locked in as the plain text is worked out, and every cipher text decrypts as far as the
+/// key stream is known.
+pub struct CribDragger {
+    cipher_texts: Vec<Vec<u8>>,
+    key_stream: Vec<Option<u8>>,
+    en: CharFreq,
+}
+
+impl CribDragger {
+    pub fn new(cipher_texts: Vec<Vec<u8>>) -> CribDragger {
+        let longest = cipher_texts.iter().map(|c| c.len()).max().unwrap_or(0);
+        CribDragger { cipher_texts, key_stream: vec![None; longest], en: CharFreq::for_english() }
+    }
+
+    pub fn cipher_texts(&self) -> &[Vec<u8>] {
+        &self.cipher_texts
+    }
+
+    pub fn key_stream(&self) -> &[Option<u8>] {
+        &self.key_stream
+    }
+
+    /// Tries crib at every offset of cipher text idx and ranks the offsets by how the other
+    /// cipher texts decrypt under the key stream it implies. Best first.
+    pub fn drag(&self, idx: usize, crib: &[u8]) -> Result<Vec<Placement>> {
+        let cipher_text = self.cipher_text(idx)?;
+        if crib.is_empty() || crib.len() > cipher_text.len() {
+            return Ok(vec!());
+        }
+
+        let mut placements: Vec<Placement> = (0..=cipher_text.len() - crib.len()).map(|offset| {
+            let key = xor_each_no_wrap(&cipher_text[offset..], crib);
+            let others: Vec<Vec<u8>> = self.cipher_texts.iter()
+                .enumerate()
+                .filter(|&(other, _)| other != idx)
+                .map(|(_, c)| xor_each_no_wrap(c.get(offset..).unwrap_or(&[]), &key))
+                .collect();
+            let score = text_score(&self.en, &others.concat());
+            Placement { offset, others, score }
+        }).collect();
+        placements.sort_by(|x, y| x.score.total_cmp(&y.score));
+        Ok(placements)
+    }
+
+    /// Takes plain as the plain text of cipher text idx at offset and locks in the key stream
+    /// under it. Nothing is locked if that contradicts key stream already locked, `unlock` it
+    /// first to overwrite it.
+    pub fn lock(&mut self, idx: usize, offset: usize, plain: &[u8]) -> Result<()> {
+        let cipher_text = self.cipher_text(idx)?;
+        if offset + plain.len() > cipher_text.len() {
+            return Err(Error::OutOfBounds(offset + plain.len()));
+        }
+
+        let keys = xor_each_no_wrap(&cipher_text[offset..], plain);
+        let locked = &self.key_stream[offset..offset + keys.len()];
+        if let Some(i) = keys.iter().zip(locked).position(|(&k, l)| l.is_some_and(|l| l != k)) {
+            return Err(Error::KeyStreamConflict(offset + i));
+        }
+        for (i, key) in keys.into_iter().enumerate() {
+            self.key_stream[offset + i] = Some(key);
+        }
+        Ok(())
+    }
+
+    /// Forgets len key stream bytes from offset on
+    pub fn unlock(&mut self, offset: usize, len: usize) {
+        let end = (offset + len).min(self.key_stream.len());
+        for key in self.key_stream[offset.min(end)..end].iter_mut() {
+            *key = None;
+        }
+    }
+
+    /// Every cipher text decrypted with what is known of the key stream
+    pub fn plain_texts(&self) -> Vec<Vec<Option<u8>>> {
+        self.cipher_texts.iter()
+            .map(|c| c.iter().zip(self.key_stream.iter()).map(|(&c, k)| k.map(|k| c ^ k)).collect())
+            .collect()
+    }
+
+    /// Cipher text idx as far as it is known, with unknown for the rest and a dot for bytes that
+    /// aren't printable
+    pub fn render(&self, idx: usize, unknown: char) -> Result<String> {
+        self.cipher_text(idx)?;
+        Ok(self.plain_texts()[idx].iter().map(|p| match *p {
+            Some(b) if (0x20..0x7f).contains(&b) => b as char,
+            Some(_) => '.',
+            None => unknown,
+        }).collect())
+    }
+
+    fn cipher_text(&self, idx: usize) -> Result<&[u8]> {
+        self.cipher_texts.get(idx).map(|c| &c[..]).ok_or(Error::OutOfBounds(idx))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypter::{ctr, random_aes_key};
+    use aes::Aes;
+
+    const MESSAGES: [&[u8]; 4] = [
+        b"attack the east wall at dawn",
+        b"hold the bridge until noon",
+        b"the supplies arrive tomorrow",
+        b"send more men",
+    ];
+
+    fn encrypted() -> Vec<Vec<u8>> {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        MESSAGES.iter().map(|m| ctr(&cipher, 0, m).unwrap()).collect()
+    }
+
+    #[test]
+    fn drag_two() {
+        let cipher_texts = encrypted();
+        let fragments = drag(&cipher_texts[0], &cipher_texts[1], b" the ");
+        assert_eq!(fragments.len(), 26 - 5 + 1);
+        // " the " sits at 6 in the first and at 4 in the second
+        let top: Vec<(usize, &[u8])> = fragments.iter().take(2).map(|f| (f.offset, &f.text[..])).collect();
+        assert_eq!(top, [(6, &b"he br"[..]), (4, &b"ck th"[..])]);
+        assert!(drag(&cipher_texts[3], &cipher_texts[0], b"a crib longer than the xor").is_empty());
+    }
+
+    #[test]
+    fn lock_and_propagate() {
+        let mut dragger = CribDragger::new(encrypted());
+
+        let best = &dragger.drag(2, b"the ").unwrap()[0];
+        assert_eq!(best.offset, 0);
+        assert_eq!(best.others[2], b"send");
+        dragger.lock(2, 0, b"the supplies").unwrap();
+        assert_eq!(dragger.render(0, '_').unwrap(), "attack the e________________");
+        assert_eq!(dragger.render(3, '_').unwrap(), "send more me_");
+
+        // filling in one message fills in the rest
+        dragger.lock(0, 12, b"east wall at dawn").unwrap_err();
+        dragger.lock(0, 11, b"east wall at dawn").unwrap();
+        let plain_texts = dragger.plain_texts();
+        for (plain, message) in plain_texts.iter().zip(MESSAGES.iter()) {
+            assert!(plain.iter().zip(message.iter()).all(|(p, m)| *p == Some(*m)));
+        }
+
+        dragger.unlock(4, 100);
+        assert_eq!(dragger.render(1, '?').unwrap(), "hold??????????????????????");
+    }
+
+    #[test]
+    fn bad_locks_and_indexes() {
+        let mut dragger = CribDragger::new(encrypted());
+        dragger.lock(0, 0, b"attack").unwrap();
+        match dragger.lock(1, 2, b"xx the") {
+            Err(Error::KeyStreamConflict(2)) => (),
+            other => panic!("expected KeyStreamConflict, got {:?}", other),
+        }
+        // nothing past the conflict got locked either
+        assert_eq!(dragger.render(1, '_').unwrap(), "hold t____________________");
+        dragger.lock(1, 0, b"hold t").unwrap();
+
+        for idx in &[4, 100] {
+            assert!(dragger.drag(*idx, b"the").is_err());
+            assert!(dragger.lock(*idx, 0, b"the").is_err());
+            assert!(dragger.render(*idx, '_').is_err());
+        }
+    }
+}
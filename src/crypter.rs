@@ -1,73 +1,489 @@
 use openssl::symm::{Crypter, Cipher, Mode};
 use rand::{thread_rng, Rng};
-use conversions::{base64_to_hex, pad_pkcs7};
-use std::collections::HashMap;
-use std::mem::transmute;
-use combine::xor_each;
+use conversions::{base64_to_hex, pad_pkcs7, Padding};
+use std::cmp::min;
+use std::sync::Mutex;
+use aes::Aes;
+use error::{Error, Result};
+
+
+/// A keyed block cipher. Modes of operation are written against this trait, so any
+/// primitive that can encrypt and decrypt a single block in place can be plugged in.
+pub trait BlockCipher {
+    fn block_size(&self) -> usize;
+    fn encrypt_block(&self, block: &mut [u8]);
+    fn decrypt_block(&self, block: &mut [u8]);
+}
+
+/// Mode of operation applied on top of a `BlockCipher`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CipherMode {
+    Ecb,
+    Cbc,
+    Ctr,
+    Cfb,
+    Ofb,
+}
+
+impl<C: BlockCipher> BlockCipher for &C {
+    fn block_size(&self) -> usize {
+        (**self).block_size()
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) {
+        (**self).encrypt_block(block)
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) {
+        (**self).decrypt_block(block)
+    }
+}
+
+/// openssl backed block cipher, the raw primitive is driven through no-padding ECB `Crypter`s
+/// that are keyed once and reused for every block. They sit behind locks so the cipher can
+/// be shared between threads like the native `Aes`.
+pub struct OpensslCipher {
+    block_size: usize,
+    // each with its own output buffer
+    encrypter: Mutex<(Crypter, Vec<u8>)>,
+    decrypter: Mutex<(Crypter, Vec<u8>)>,
+}
+
+impl OpensslCipher {
+    pub fn new(cipher: Cipher, key: &[u8]) -> Result<OpensslCipher> {
+        if cipher.key_len() != key.len() {
+            return Err(Error::InvalidKeyLength(key.len()));
+        }
+        let block_size = cipher.block_size();
+
+        let keyed = |mode| -> Result<Mutex<(Crypter, Vec<u8>)>> {
+            let mut c = Crypter::new(cipher, mode, key, None)?;
+            c.pad(false);
+            Ok(Mutex::new((c, vec![0; 2 * block_size])))
+        };
+        Ok(OpensslCipher { block_size, encrypter: keyed(Mode::Encrypt)?, decrypter: keyed(Mode::Decrypt)? })
+    }
+
+    /// picks AES-128/192/256 based on the length of the key
+    pub fn aes(key: &[u8]) -> Result<OpensslCipher> {
+        let cipher = match key.len() {
+            16 => Cipher::aes_128_ecb(),
+            24 => Cipher::aes_192_ecb(),
+            32 => Cipher::aes_256_ecb(),
+            len => return Err(Error::InvalidKeyLength(len)),
+        };
+        Self::new(cipher, key)
+    }
+
+    fn process(crypter: &Mutex<(Crypter, Vec<u8>)>, block: &mut [u8]) {
+        // a panic elsewhere can't leave a no-padding ECB crypter half way through a block
+        let mut guard = crypter.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (ref mut crypter, ref mut output) = *guard;
+        let count = crypter.update(block, output).unwrap();
+        block.copy_from_slice(&output[..count]);
+    }
+}
+
+impl BlockCipher for OpensslCipher {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
 
+    fn encrypt_block(&self, block: &mut [u8]) {
+        Self::process(&self.encrypter, block)
+    }
 
-pub fn aes_ecb(key: &[u8], input: &[u8], iv: Option<&[u8]>, msg: &mut Vec<u8>, mode: Mode) -> usize{
-    let mut c = Crypter::new(
-        Cipher::aes_128_ecb(),
-        mode,
-        key,
-        iv
-    ).unwrap();
-    c.pad(false);
+    fn decrypt_block(&self, block: &mut [u8]) {
+        Self::process(&self.decrypter, block)
+    }
+}
 
-    let mut count = c.update(&input, &mut *msg).unwrap();
-    count += c.finalize(&mut msg[count..]).unwrap();
-    msg.truncate(count);
-    count
+/// Keyed CBC context. The chaining block carries over between calls, so a long message
+/// can be fed through in block aligned pieces without re-keying or allocating.
+pub struct Cbc<C: BlockCipher> {
+    cipher: C,
+    prev: Vec<u8>,
+    scratch: Vec<u8>,
 }
 
-pub fn aes_cbc(key: &[u8], input: &[u8], iv: Option<&[u8]>, msg: &mut Vec<u8>, mode: Mode) -> usize {
+impl<C: BlockCipher> Cbc<C> {
+    pub fn new(cipher: C, iv: &[u8]) -> Result<Cbc<C>> {
+        let block_size = cipher.block_size();
+        if iv.len() != block_size {
+            return Err(Error::InvalidIvLength(iv.len()));
+        }
+        Ok(Cbc { cipher, prev: iv.to_vec(), scratch: vec![0; block_size] })
+    }
 
-    let block_size = Cipher::aes_128_ecb().block_size();
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
 
-	let mut count = 0;
-	let mut prev = iv.unwrap().to_vec();
+    pub fn encrypt_blocks(&mut self, data: &mut [u8]) -> Result<()> {
+        let block_size = self.cipher.block_size();
+        if !data.len().is_multiple_of(block_size) {
+            return Err(Error::NotBlockAligned(data.len()));
+        }
 
-    for block in input.chunks(block_size){
-    	let mut tmp = vec![0 as u8; block.len() + block_size].to_owned();
-    	count += match mode {
-    	    Mode::Encrypt => {
-    	    	let c = aes_ecb(key, &xor_each(&block, &prev), None, &mut tmp, mode);
-    	    	tmp.truncate(c);
-    	    	prev = tmp.to_owned();
-    	    	c
-    	    },
-    	    Mode::Decrypt => {
-    	    	let c = aes_ecb(key, &block, None, &mut tmp, mode);
-    	    	tmp.truncate(c);
-    	    	tmp = xor_each(&tmp, &prev);
-    	    	prev = block.to_owned();
-    	    	c
-    	    },
-    	};
-    	msg.extend(&tmp);
+        for block in data.chunks_mut(block_size) {
+            xor_in_place(block, &self.prev);
+            self.cipher.encrypt_block(block);
+            self.prev.copy_from_slice(block);
+        }
+        Ok(())
+    }
+
+    pub fn decrypt_blocks(&mut self, data: &mut [u8]) -> Result<()> {
+        let block_size = self.cipher.block_size();
+        if !data.len().is_multiple_of(block_size) {
+            return Err(Error::NotBlockAligned(data.len()));
+        }
+
+        for block in data.chunks_mut(block_size) {
+            self.scratch.copy_from_slice(block);
+            self.cipher.decrypt_block(block);
+            xor_in_place(block, &self.prev);
+            self.prev.copy_from_slice(&self.scratch);
+        }
+        Ok(())
+    }
+
+    pub fn process(&mut self, data: &mut [u8], mode: Mode) -> Result<()> {
+        match mode {
+            Mode::Encrypt => self.encrypt_blocks(data),
+            Mode::Decrypt => self.decrypt_blocks(data),
+        }
     }
-    msg.truncate(count);
-    count
 }
 
-pub fn aes_ctr(key: &[u8], input: &[u8], nonce: u64, msg: &mut Vec<u8>) -> usize {
-    for (idx, chunk) in input.chunks(key.len()).enumerate() {
-        let mut nonce_bytes: [u8; 8] = unsafe { transmute(nonce as u64) };
-        let mut block_count: [u8; 8] = unsafe { transmute((idx as u64).to_be()) };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
 
-        nonce_bytes.reverse();
-        block_count.reverse();
+/// Layout of a CTR counter block: fixed bytes (nonce, IV) followed by a counter of `width`
+/// bytes. Block n carries the counter `initial + n`, wrapping modulo 2^(8 * width).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterBlock {
+    fixed: Vec<u8>,
+    initial: u128,
+    width: usize,
+    endian: Endian,
+}
 
-        let key_stream = &[nonce_bytes, block_count].concat();
-        let mut output = vec![0 as u8; key_stream.len() + key.len()].to_owned();
+impl CounterBlock {
+    pub fn new(fixed: &[u8], initial: u128, width: usize, endian: Endian) -> Result<CounterBlock> {
+        if width == 0 || width > 16 {
+            return Err(Error::InvalidCounterWidth(width));
+        }
+        Ok(CounterBlock { fixed: fixed.to_vec(), initial, width, endian })
+    }
+
+    /// 64 bit little endian nonce followed by a 64 bit little endian block count (cryptopals)
+    pub fn little_endian_64(nonce: u64) -> CounterBlock {
+        CounterBlock { fixed: nonce.to_le_bytes().to_vec(), initial: 0, width: 8, endian: Endian::Little }
+    }
+
+    /// NIST SP 800-38A: the whole initial counter block is given, only its last `width`
+    /// bytes are incremented as a big endian integer (usually 4)
+    pub fn nist(initial_block: &[u8], width: usize) -> Result<CounterBlock> {
+        if width > initial_block.len() {
+            return Err(Error::InvalidCounterWidth(width));
+        }
+        let (fixed, counter) = initial_block.split_at(initial_block.len() - width);
+        let initial = counter.iter().fold(0u128, |acc, &b| (acc << 8) | b as u128);
+        Self::new(fixed, initial, width, Endian::Big)
+    }
+
+    /// RFC 3686: 32 bit nonce | 64 bit IV | 32 bit big endian counter starting at one
+    pub fn rfc3686(nonce: &[u8; 4], iv: &[u8; 8]) -> CounterBlock {
+        CounterBlock { fixed: [&nonce[..], &iv[..]].concat(), initial: 1, width: 4, endian: Endian::Big }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fixed.len() + self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// writes the counter block for block index into out
+    pub fn write(&self, index: u64, out: &mut [u8]) {
+        let value = self.initial.wrapping_add(index as u128);
+        let (fixed, counter) = out.split_at_mut(self.fixed.len());
+        fixed.copy_from_slice(&self.fixed);
+
+        let bytes = value.to_le_bytes();
+        let counter_bytes = &bytes[..self.width];
+        match self.endian {
+            Endian::Little => counter.copy_from_slice(counter_bytes),
+            Endian::Big => {
+                for (c, b) in counter.iter_mut().zip(counter_bytes.iter().rev()) {
+                    *c = *b;
+                }
+            },
+        }
+    }
+}
+
+/// Keyed CTR context. Keeps track of the position in the keystream so input can be fed
+/// through in pieces of any size, and can seek to any byte offset for random access.
+pub struct Ctr<C: BlockCipher> {
+    cipher: C,
+    counter_block: CounterBlock,
+    counter: u64,
+    key_stream: Vec<u8>,
+    used: usize,
+}
+
+impl<C: BlockCipher> Ctr<C> {
+    // cryptopals layout, see CounterBlock::little_endian_64
+    pub fn new(cipher: C, nonce: u64) -> Result<Ctr<C>> {
+        Self::with_counter_block(cipher, CounterBlock::little_endian_64(nonce))
+    }
+
+    /// The counter block has to be as long as a cipher block
+    pub fn with_counter_block(cipher: C, counter_block: CounterBlock) -> Result<Ctr<C>> {
+        let block_size = cipher.block_size();
+        if counter_block.len() != block_size {
+            return Err(Error::InvalidIvLength(counter_block.len()));
+        }
+        Ok(Ctr { cipher, counter_block, counter: 0, key_stream: vec![0; block_size], used: block_size })
+    }
+
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    fn next_key_stream_block(&mut self) {
+        self.counter_block.write(self.counter, &mut self.key_stream);
+        self.cipher.encrypt_block(&mut self.key_stream);
+
+        self.counter = self.counter.wrapping_add(1);
+        self.used = 0;
+    }
+
+    // encryption and decryption are the same operation
+    pub fn apply_key_stream(&mut self, data: &mut [u8]) {
+        let block_size = self.key_stream.len();
+        let mut idx = 0;
+        while idx < data.len() {
+            if self.used == block_size {
+                self.next_key_stream_block();
+            }
+            let take = min(block_size - self.used, data.len() - idx);
+            xor_in_place(&mut data[idx..idx + take], &self.key_stream[self.used..self.used + take]);
+            self.used += take;
+            idx += take;
+        }
+    }
+
+    /// byte offset into the keystream that the next byte will be xored with
+    pub fn position(&self) -> u64 {
+        let block_size = self.key_stream.len() as u64;
+        self.counter.wrapping_mul(block_size).wrapping_sub(block_size - self.used as u64)
+    }
+
+    pub fn seek(&mut self, offset: u64) {
+        let block_size = self.key_stream.len() as u64;
+        self.counter = offset / block_size;
+        self.used = block_size as usize;
+
+        let within = (offset % block_size) as usize;
+        if within > 0 {
+            self.next_key_stream_block();
+            self.used = within;
+        }
+    }
+
+    /// keystream for the byte range [offset, offset + len), leaves the context positioned
+    /// right after it
+    pub fn key_stream_at(&mut self, offset: u64, len: usize) -> Vec<u8> {
+        let mut key_stream = vec![0; len];
+        self.seek(offset);
+        self.apply_key_stream(&mut key_stream);
+        key_stream
+    }
+
+    /// Replaces the plain text underneath cipher_text at offset with new_text, only the
+    /// touched keystream blocks are generated. Writing past the end grows the cipher text.
+    pub fn edit(&mut self, cipher_text: &mut Vec<u8>, offset: u64, new_text: &[u8]) -> Result<()> {
+        if offset > cipher_text.len() as u64 {
+            return Err(Error::OutOfBounds(offset as usize));
+        }
+        let start = offset as usize;
+        let end = start + new_text.len();
+        if end > cipher_text.len() {
+            cipher_text.resize(end, 0);
+        }
 
-        // it's actually irrelevant what the mode is, as long as encryption and decryption is the same
-        aes_ecb(key, key_stream, None, &mut output, Mode::Encrypt);
-        let xored = xor_each(chunk, &output);
-        msg.extend(xored);
+        cipher_text[start..end].copy_from_slice(new_text);
+        self.seek(offset);
+        self.apply_key_stream(&mut cipher_text[start..end]);
+        Ok(())
     }
-    msg.len()
+}
+
+// input has to be a multiple of the block size, pad it before calling
+pub fn ecb<C: BlockCipher>(cipher: &C, input: &[u8], mode: Mode) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    if !input.len().is_multiple_of(block_size) {
+        return Err(Error::NotBlockAligned(input.len()));
+    }
+
+    let mut output = input.to_vec();
+    for block in output.chunks_mut(block_size) {
+        match mode {
+            Mode::Encrypt => cipher.encrypt_block(block),
+            Mode::Decrypt => cipher.decrypt_block(block),
+        }
+    }
+    Ok(output)
+}
+
+// input has to be a multiple of the block size, pad it before calling
+pub fn cbc<C: BlockCipher>(cipher: &C, iv: &[u8], input: &[u8], mode: Mode) -> Result<Vec<u8>> {
+    let mut output = input.to_vec();
+    Cbc::new(cipher, iv)?.process(&mut output, mode)?;
+    Ok(output)
+}
+
+pub fn ctr<C: BlockCipher>(cipher: &C, nonce: u64, input: &[u8]) -> Result<Vec<u8>> {
+    ctr_with(cipher, CounterBlock::little_endian_64(nonce), input)
+}
+
+pub fn ctr_with<C: BlockCipher>(cipher: &C, counter_block: CounterBlock, input: &[u8]) -> Result<Vec<u8>> {
+    let mut output = input.to_vec();
+    Ctr::with_counter_block(cipher, counter_block)?.apply_key_stream(&mut output);
+    Ok(output)
+}
+
+// full block cipher feedback, the final block may be partial
+pub fn cfb<C: BlockCipher>(cipher: &C, iv: &[u8], input: &[u8], mode: Mode) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    if iv.len() != block_size {
+        return Err(Error::InvalidIvLength(iv.len()));
+    }
+
+    let mut output = input.to_vec();
+    let mut feedback = iv.to_vec();
+    for chunk in output.chunks_mut(block_size) {
+        cipher.encrypt_block(&mut feedback);
+        let len = chunk.len();
+        match mode {
+            Mode::Encrypt => {
+                xor_in_place(chunk, &feedback);
+                feedback[..len].copy_from_slice(chunk);
+            },
+            Mode::Decrypt => {
+                // feedback becomes the cipher text, chunk becomes the plain text
+                for (c, f) in chunk.iter_mut().zip(feedback.iter_mut()) {
+                    let cipher_byte = *c;
+                    *c ^= *f;
+                    *f = cipher_byte;
+                }
+            },
+        }
+    }
+    Ok(output)
+}
+
+// output feedback, encryption and decryption are the same operation
+pub fn ofb<C: BlockCipher>(cipher: &C, iv: &[u8], input: &[u8]) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    if iv.len() != block_size {
+        return Err(Error::InvalidIvLength(iv.len()));
+    }
+
+    let mut output = input.to_vec();
+    let mut key_stream = iv.to_vec();
+    for chunk in output.chunks_mut(block_size) {
+        cipher.encrypt_block(&mut key_stream);
+        xor_in_place(chunk, &key_stream);
+    }
+    Ok(output)
+}
+
+/// Single entry point over every mode. For CTR the iv is the 8 byte little endian nonce and
+/// defaults to zero, ECB ignores it and the feedback modes require it.
+pub fn crypt<C: BlockCipher>(cipher: &C, cipher_mode: CipherMode, iv: Option<&[u8]>, input: &[u8], mode: Mode) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    match cipher_mode {
+        CipherMode::Ecb => ecb(cipher, input, mode),
+        CipherMode::Ctr => {
+            let mut nonce = [0; 8];
+            if let Some(iv) = iv {
+                nonce.copy_from_slice(block_iv(Some(iv), 8)?);
+            }
+            ctr(cipher, u64::from_le_bytes(nonce), input)
+        },
+        CipherMode::Cbc | CipherMode::Cfb | CipherMode::Ofb => {
+            let iv = block_iv(iv, block_size)?;
+            match cipher_mode {
+                CipherMode::Cbc => cbc(cipher, iv, input, mode),
+                CipherMode::Cfb => cfb(cipher, iv, input, mode),
+                _ => ofb(cipher, iv, input),
+            }
+        },
+    }
+}
+
+/// `crypt` with a padding scheme, applied before encrypting and checked and stripped after
+/// decrypting. This is how ECB and CBC get to run on unaligned input.
+pub fn crypt_padded<C: BlockCipher, P: Padding + ?Sized>(cipher: &C, cipher_mode: CipherMode, padding: &P, iv: Option<&[u8]>, input: &[u8], mode: Mode) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    match mode {
+        Mode::Encrypt => {
+            let mut padded = input.to_vec();
+            padding.pad(&mut padded, block_size)?;
+            crypt(cipher, cipher_mode, iv, &padded, mode)
+        },
+        Mode::Decrypt => {
+            let mut output = crypt(cipher, cipher_mode, iv, input, mode)?;
+            padding.unpad(&mut output, block_size)?;
+            Ok(output)
+        },
+    }
+}
+
+fn block_iv(iv: Option<&[u8]>, block_size: usize) -> Result<&[u8]> {
+    match iv {
+        Some(iv) if iv.len() == block_size => Ok(iv),
+        Some(iv) => Err(Error::InvalidIvLength(iv.len())),
+        None => Err(Error::MissingIv),
+    }
+}
+
+fn xor_in_place(block: &mut [u8], with: &[u8]) {
+    for (a, b) in block.iter_mut().zip(with.iter()) {
+        *a ^= b;
+    }
+}
+
+// the aes_* helpers run on the native aes::Aes backend, they all append their output to msg
+// and return the number of bytes written
+
+pub fn aes_ecb(key: &[u8], input: &[u8], _iv: Option<&[u8]>, msg: &mut Vec<u8>, mode: Mode) -> Result<usize> {
+    msg.extend(ecb(&Aes::new(key)?, input, mode)?);
+    Ok(input.len())
+}
+
+pub fn aes_cbc(key: &[u8], input: &[u8], iv: Option<&[u8]>, msg: &mut Vec<u8>, mode: Mode) -> Result<usize> {
+    let cipher = Aes::new(key)?;
+    let iv = block_iv(iv, cipher.block_size())?;
+    msg.extend(cbc(&cipher, iv, input, mode)?);
+    Ok(input.len())
+}
+
+pub fn aes_ctr(key: &[u8], input: &[u8], nonce: u64, msg: &mut Vec<u8>) -> Result<usize> {
+    let mut context = Ctr::new(Aes::new(key)?, nonce)?;
+
+    let start = msg.len();
+    msg.extend_from_slice(input);
+    context.apply_key_stream(&mut msg[start..]);
+    Ok(input.len())
 }
 
 pub fn random_aes_key() -> Vec<u8> {
@@ -81,7 +497,8 @@ pub fn random_bytes(min: usize, max: usize) -> Vec<u8> {
     rng.gen_iter::<u8>().take(len).collect::<Vec<u8>>()
 }
 
-pub fn encryption_oracle(plaintext: &[u8], msg: &mut Vec<u8>) -> String {
+/// Encrypts under a random key with ECB or CBC, picked at random, and reports which
+pub fn encryption_oracle(plaintext: &[u8], msg: &mut Vec<u8>) -> Result<CipherMode> {
     let mut rng = thread_rng();
 
     let key = random_aes_key();
@@ -90,25 +507,18 @@ pub fn encryption_oracle(plaintext: &[u8], msg: &mut Vec<u8>) -> String {
 
     // pad the plaintext
     let mut padded_plaintext = plaintext.to_owned().to_vec();
-    pad_pkcs7(&mut padded_plaintext, key_size);
-
-    // allocate for msg
-    msg.extend(vec![0; padded_plaintext.len() + key_size]);
-
-    let mode: &str;
-    let fnc = if rng.gen::<bool>() {
-        mode = "ECB";
-        aes_ecb
-    } else {
-        msg.clear();
-        mode = "CBC";
-        aes_cbc
+    pad_pkcs7(&mut padded_plaintext, key_size)?;
+
+    let mode = if rng.gen::<bool>() { CipherMode::Ecb } else { CipherMode::Cbc };
+    let fnc = match mode {
+        CipherMode::Ecb => aes_ecb,
+        _ => aes_cbc,
     };
-    fnc(&key, &padded_plaintext, Some(&iv), &mut *msg, Mode::Encrypt);
-    String::from(mode)
+    fnc(&key, &padded_plaintext, Some(&iv), &mut *msg, Mode::Encrypt)?;
+    Ok(mode)
 }
 
-pub fn consistent_ecb(key: &Vec<u8>, prefix: &Vec<u8>, plaintext: &[u8], msg: &mut Vec<u8>) -> usize {
+pub fn consistent_ecb(key: &Vec<u8>, prefix: &Vec<u8>, plaintext: &[u8], msg: &mut Vec<u8>) -> Result<usize> {
     let suffix = String::from("Um9sbGluJyBpbiBteSA1LjAKV2l0aCBteSByYWctdG9wIGRvd24gc28gbXkg\
     aGFpciBjYW4gYmxvdwpUaGUgZ2lybGllcyBvbiBzdGFuZGJ5IHdhdmluZyBq\
     dXN0IHRvIHNheSBoaQpEaWQgeW91IHN0b3A/IE5vLCBJIGp1c3QgZHJvdmUgYnkK");
@@ -117,136 +527,265 @@ pub fn consistent_ecb(key: &Vec<u8>, prefix: &Vec<u8>, plaintext: &[u8], msg: &m
     mod_plaintext.extend(prefix);
     mod_plaintext.extend(plaintext);
 
-    mod_plaintext.extend(base64_to_hex(suffix));
-    pad_pkcs7(&mut mod_plaintext, key.len());
-    msg.extend(vec![0; mod_plaintext.len() + key.len()]);
+    mod_plaintext.extend(base64_to_hex(&suffix)?);
+    pad_pkcs7(&mut mod_plaintext, key.len())?;
     aes_ecb(&key, &mod_plaintext, None, &mut *msg, Mode::Encrypt)
 }
 
-// challenge 12
-fn find_block_size(key: &Vec<u8>, plain_text: &mut Vec<u8>) -> usize {
-    let block_size;
-    let mut cipher_text = vec!();
-    consistent_ecb(&key, &vec!(), &plain_text, &mut cipher_text);
-    loop {
-        plain_text.push(0);
-        let cipher_size = cipher_text.len();
-        let count = consistent_ecb(&key, &vec!(), &plain_text, &mut cipher_text);
-        if count != cipher_size{
-            block_size = count - cipher_size; 
-            break;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conversions::string_to_hex;
+
+    // NIST SP 800-38A, F.1 - F.4 (AES-128)
+    const KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const PLAIN: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+
+    fn check(cipher_mode: CipherMode, expected: &str) {
+        let cipher = OpensslCipher::aes(&string_to_hex(KEY).unwrap()).unwrap();
+        let iv = string_to_hex(IV).unwrap();
+        let plain = string_to_hex(PLAIN).unwrap();
+
+        let encrypted = crypt(&cipher, cipher_mode, Some(&iv), &plain, Mode::Encrypt).unwrap();
+        assert_eq!(encrypted, string_to_hex(expected).unwrap());
+        assert_eq!(crypt(&cipher, cipher_mode, Some(&iv), &encrypted, Mode::Decrypt).unwrap(), plain);
+    }
+
+    #[test]
+    fn nist_vectors() {
+        check(CipherMode::Ecb, "3ad77bb40d7a3660a89ecaf32466ef97f5d3d58503b9699de785895a96fdbaaf");
+        check(CipherMode::Cbc, "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2");
+        check(CipherMode::Cfb, "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b");
+        check(CipherMode::Ofb, "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed825");
+    }
+
+    #[test]
+    fn stream_modes_partial_block() {
+        let cipher = OpensslCipher::aes(b"YELLOW SUBMARINE").unwrap();
+        let iv = [7u8; 16];
+        let plain = b"twenty one bytes long".to_vec();
+
+        for &cipher_mode in [CipherMode::Ctr, CipherMode::Cfb, CipherMode::Ofb].iter() {
+            // CTR takes an 8 byte nonce
+            let iv = if cipher_mode == CipherMode::Ctr { &iv[..8] } else { &iv[..] };
+            let encrypted = crypt(&cipher, cipher_mode, Some(iv), &plain, Mode::Encrypt).unwrap();
+            assert_eq!(encrypted.len(), plain.len());
+            assert_eq!(crypt(&cipher, cipher_mode, Some(iv), &encrypted, Mode::Decrypt).unwrap(), plain);
         }
     }
-    block_size
-}
 
-fn confirm_ecb(block_size: usize, key: &Vec<u8>, plain_text: &mut Vec<u8>) {
-    plain_text.clear();
-    plain_text.extend(vec![0; 3*block_size]);
+    #[test]
+    fn contexts_stream_across_calls() {
+        let cipher = OpensslCipher::aes(b"YELLOW SUBMARINE").unwrap();
+        let iv = [3u8; 16];
+        let plain = random_bytes(100, 100);
 
-    let mut cipher_text = vec!();
-    consistent_ecb(&key, &vec!(), &plain_text, &mut cipher_text);
-    assert_eq!(cipher_text[0..16], cipher_text[16..32]);
-}
+        let mut context = Ctr::new(&cipher, 5).unwrap();
+        let mut streamed = plain.clone();
+        for chunk in streamed.chunks_mut(7) {
+            context.apply_key_stream(chunk);
+        }
+        assert_eq!(streamed, ctr(&cipher, 5, &plain).unwrap());
 
-fn generate_next_byte(generated_key: &Vec<u8>, block_size: usize, prefix: &Vec<u8>, prefix_size: usize) -> String {
-    let mut decrypted: Vec<u8> = vec!(); 
-    let mut cipher_text: Vec<u8> = vec!();
-    let mut plain_text: Vec<u8> = vec!();
+        let mut context = Cbc::new(&cipher, &iv).unwrap();
+        let mut streamed = plain[..96].to_vec();
+        for chunk in streamed.chunks_mut(32) {
+            context.encrypt_blocks(chunk).unwrap();
+        }
+        assert_eq!(streamed, cbc(&cipher, &iv, &plain[..96], Mode::Encrypt).unwrap());
+    }
 
-    // the prefix padding at the end
-    let prefix_pad_size = if prefix_size > 0 { block_size - (prefix_size % block_size) } else { 0 };
-    let prefix_len_round_down = prefix_size - (prefix_size % block_size);
+    #[test]
+    fn ctr_random_access() {
+        let cipher = OpensslCipher::aes(b"YELLOW SUBMARINE").unwrap();
+        let plain = random_bytes(200, 200);
+        let encrypted = ctr(&cipher, 0, &plain).unwrap();
+        let key_stream = ctr(&cipher, 0, &[0; 200]).unwrap();
+
+        let mut context = Ctr::new(&cipher, 0).unwrap();
+        assert_eq!(context.key_stream_at(37, 50), &key_stream[37..87]);
+        assert_eq!(context.position(), 87);
+
+        context.seek(160);
+        let mut tail = encrypted[160..].to_vec();
+        context.apply_key_stream(&mut tail);
+        assert_eq!(tail, &plain[160..]);
+
+        let mut edited = encrypted.clone();
+        context.edit(&mut edited, 190, b"past the end").unwrap();
+        assert_eq!(edited.len(), 202);
+        assert_eq!(&edited[..190], &encrypted[..190]);
+        assert_eq!(ctr(&cipher, 0, &edited).unwrap()[190..], b"past the end"[..]);
+    }
 
-    // will always be a multiple of 16
-    let encrypted_prefix_len = prefix_pad_size + prefix_len_round_down;
-    
-    // solve, we can systemically replace a single value of known spot in the plaintext
-    loop {
-        let mut dict: HashMap<Vec<u8>, u8> = HashMap::new();
-        let decrypted_pad_size = block_size - (decrypted.len() % block_size);
-
-        // we need to prepad the prefix so that we always know the last character
-        plain_text.clear();
-        plain_text.extend(vec![0; prefix_pad_size + decrypted_pad_size - 1]);
-        for last_byte in 0x00..0xFF {
-            cipher_text.clear();
-            let try_plain_text = [&plain_text[..], &decrypted[..], &[last_byte]].concat();
-            consistent_ecb(&generated_key, prefix, &try_plain_text, &mut cipher_text);
-            dict.insert(cipher_text[encrypted_prefix_len..encrypted_prefix_len+try_plain_text.len()].to_owned(), last_byte);
-        }
-
-        cipher_text.clear();
-        consistent_ecb(&generated_key, prefix, &plain_text, &mut cipher_text);
-        if prefix_size + decrypted.len() + plain_text.len()+1 >= cipher_text.len() {
-            break;
-        }   
-        let query = cipher_text[encrypted_prefix_len..encrypted_prefix_len+decrypted.len()+plain_text.len()+1].to_vec();
-        
-        if let Some(next_byte) = dict.get(&query) {
-            decrypted.push(*next_byte);
-        } else {
-            break;
-        }
-    }
-    String::from_utf8(decrypted).unwrap()
-}
-
-// challenge 14
-fn ecb_match_blocks(key: &Vec<u8>, prefix: &[u8], block_size: usize) -> usize {
-    let mut plain_text = vec!();
-    
-    plain_text.extend(prefix);
+    fn check_ctr(key: &str, counter_block: CounterBlock, plain: &str, expected: &str) {
+        let encrypted = ctr_with(&Aes::new(&string_to_hex(key).unwrap()).unwrap(), counter_block, &string_to_hex(plain).unwrap()).unwrap();
+        assert_eq!(encrypted, string_to_hex(expected).unwrap());
+    }
 
-    let mut digest1 = vec!();
-    let mut digest2 = vec!();
+    #[test]
+    fn ctr_nist_layout() {
+        // SP 800-38A F.5.1, F.5.3 and F.5.5
+        let initial = string_to_hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap();
+        check_ctr(KEY, CounterBlock::nist(&initial, 4).unwrap(), PLAIN,
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff");
+        check_ctr("8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b", CounterBlock::nist(&initial, 4).unwrap(), PLAIN,
+            "1abc932417521ca24f2b0459fe7e6e0b090339ec0aa6faefd5ccc2c6f4ce8e94");
+        check_ctr("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4", CounterBlock::nist(&initial, 4).unwrap(), PLAIN,
+            "601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5");
+    }
 
-    consistent_ecb(key, &vec!(), &plain_text, &mut digest1);    
-    plain_text.push(0);
-    consistent_ecb(key, &vec!(), &plain_text, &mut digest2);
+    #[test]
+    fn ctr_rfc3686_layout() {
+        // RFC 3686 section 6, test vectors 1, 2, 4 and 7
+        check_ctr("ae6852f8121067cc4bf7a5765577f39e", CounterBlock::rfc3686(&[0, 0, 0, 0x30], &[0; 8]),
+            "53696e676c6520626c6f636b206d7367", "e4095d4fb7a7b3792d6175a3261311b8");
+        check_ctr("7e24067817fae0d743d6ce1f32539163",
+            CounterBlock::rfc3686(&[0x00, 0x6c, 0xb6, 0xdb], &[0xc0, 0x54, 0x3b, 0x59, 0xda, 0x48, 0xd9, 0x0b]),
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            "5104a106168a72d9790d41ee8edad388eb2e1efc46da57c8fce630df9141be28");
+        check_ctr("16af5b145fc9f579c175f93e3bfb0eed863d06ccfdb78515",
+            CounterBlock::rfc3686(&[0, 0, 0, 0x48], &[0x36, 0x73, 0x3c, 0x14, 0x7d, 0x6d, 0x93, 0xcb]),
+            "53696e676c6520626c6f636b206d7367", "4b55384fe259c9c84e7935a003cbe928");
+        check_ctr("776beff2851db06f4c8a0542c8696f6c6a81af1eec96b4d37fc1d689e6c1c104",
+            CounterBlock::rfc3686(&[0, 0, 0, 0x60], &[0xdb, 0x56, 0x72, 0xc9, 0x7a, 0xa8, 0xf0, 0xb2]),
+            "53696e676c6520626c6f636b206d7367", "145ad01dbf824ec7560863dc71e3e0c0");
+    }
 
-    let (mut c1, mut c2) = (digest1.chunks(block_size), digest2.chunks(block_size));
-    for i in 0..c1.len(){
-        if c1.next().unwrap() != c2.next().unwrap() {
-            return i;
-        }
+    #[test]
+    fn ctr_counter_wraps_within_width() {
+        // a one byte counter starting at 0xff wraps to 0x00 without carrying into the IV
+        let counter_block = CounterBlock::nist(&[0xff; 16], 1).unwrap();
+        let mut block = [0u8; 16];
+        counter_block.write(1, &mut block);
+        assert_eq!(&block[..15], &[0xff; 15]);
+        assert_eq!(block[15], 0);
+
+        // the full width big endian counter matches openssl's aes-128-ctr
+        let (key, iv) = (random_bytes(16, 16), random_bytes(16, 16));
+        let plain = random_bytes(100, 100);
+        let expected = ::openssl::symm::encrypt(Cipher::aes_128_ctr(), &key, Some(&iv), &plain).unwrap();
+        assert_eq!(ctr_with(&Aes::new(&key).unwrap(), CounterBlock::nist(&iv, 16).unwrap(), &plain).unwrap(), expected);
     }
-    0
-}
 
-fn find_prefix_size(key: &Vec<u8>, prefix: &[u8], block_size: usize) -> usize {
-    // so we know that ECB is the same for blocks only
-    // 1. find all the same blocks first, then find left over blocks
-    
-    let blocks_matched = ecb_match_blocks(key, prefix, block_size);
+    #[test]
+    fn openssl_cipher_across_threads() {
+        use parallel::Parallel;
+
+        let key = random_bytes(16, 16);
+        let (openssl, native) = (OpensslCipher::aes(&key).unwrap(), Aes::new(&key).unwrap());
+        let blocks: Vec<Vec<u8>> = (0..64).map(|_| random_bytes(16, 16)).collect();
+        let encrypted = Parallel::new(8).map(&blocks, |block| {
+            let mut block = block.clone();
+            openssl.encrypt_block(&mut block);
+            block
+        });
+        for (block, encrypted) in blocks.iter().zip(encrypted.iter()) {
+            assert_eq!(encrypted, &ecb(&native, block, Mode::Encrypt).unwrap());
+        }
+    }
 
-    let mut mod_prefix = prefix.to_vec();
+    #[test]
+    fn ctr_larger_keys() {
+        // the keystream is chunked by block size, not by key size
+        let cipher = OpensslCipher::aes(&[1u8; 32]).unwrap();
+        let plain = vec![0u8; 40];
+        let key_stream = ctr(&cipher, 0, &plain).unwrap();
+
+        let mut second_block = [0u8; 16];
+        second_block[8] = 1;
+        cipher.encrypt_block(&mut second_block);
+        assert_eq!(&key_stream[16..32], &second_block[..]);
+    }
 
-    for i in 0..16 {
-        mod_prefix.insert(0,0);
+    #[test]
+    fn padded_modes() {
+        use conversions::{Pkcs7, AnsiX923, Iso10126, Iso7816};
+
+        let cipher = Aes::new(b"YELLOW SUBMARINE").unwrap();
+        let iv = [3u8; 16];
+        let plain = random_bytes(45, 45);
+        let schemes: [&dyn Padding; 4] = [&Pkcs7, &AnsiX923, &Iso10126, &Iso7816];
+        for padding in schemes.iter() {
+            for &cipher_mode in &[CipherMode::Ecb, CipherMode::Cbc] {
+                let encrypted = crypt_padded(&cipher, cipher_mode, *padding, Some(&iv), &plain, Mode::Encrypt).unwrap();
+                assert_eq!(encrypted.len(), 48);
+                assert_eq!(crypt_padded(&cipher, cipher_mode, *padding, Some(&iv), &encrypted, Mode::Decrypt).unwrap(), plain);
+            }
+        }
 
-        let new_matched = ecb_match_blocks(key, &mod_prefix, block_size);
-        if new_matched != blocks_matched {
-            return blocks_matched * block_size + block_size - i - 1;
+        // X.923 requires zeros where PKCS#7 has the length
+        let encrypted = crypt_padded(&cipher, CipherMode::Cbc, &Pkcs7, Some(&iv), &plain, Mode::Encrypt).unwrap();
+        match crypt_padded(&cipher, CipherMode::Cbc, &AnsiX923, Some(&iv), &encrypted, Mode::Decrypt) {
+            Err(Error::Padding(_)) => (),
+            other => panic!("expected a padding error, got {:?}", other),
         }
     }
-    blocks_matched * block_size
-}
 
-pub fn ecb_oracle(plain_text: &mut Vec<u8>, prefix: Vec<u8>) -> String {
-    let generated_key = random_aes_key();
-    
-    // 1. find block size
-    let block_size = find_block_size(&generated_key, plain_text);
-    assert_eq!(block_size, 16);
+    #[test]
+    fn aes_helper_errors() {
+        let (key, mut msg) = (b"YELLOW SUBMARINE", vec!());
+        match aes_cbc(key, &[0; 32], None, &mut msg, Mode::Decrypt) {
+            Err(Error::MissingIv) => (),
+            other => panic!("expected MissingIv, got {:?}", other),
+        }
+        match aes_cbc(key, &[0; 32], Some(&[0; 8]), &mut msg, Mode::Decrypt) {
+            Err(Error::InvalidIvLength(8)) => (),
+            other => panic!("expected InvalidIvLength, got {:?}", other),
+        }
+        match aes_ecb(key, &[0; 20], None, &mut msg, Mode::Encrypt) {
+            Err(Error::NotBlockAligned(20)) => (),
+            other => panic!("expected NotBlockAligned, got {:?}", other),
+        }
+        match aes_ctr(b"YELLOW", &[0; 20], 0, &mut msg) {
+            Err(Error::InvalidKeyLength(6)) => (),
+            other => panic!("expected InvalidKeyLength, got {:?}", other),
+        }
+        assert!(msg.is_empty());
 
-    // 2. confirm that it is ECB
-    confirm_ecb(block_size, &generated_key, plain_text);
+        let cipher = Aes::new(key).unwrap();
+        match crypt(&cipher, CipherMode::Ofb, None, &[0; 5], Mode::Encrypt) {
+            Err(Error::MissingIv) => (),
+            other => panic!("expected MissingIv, got {:?}", other),
+        }
+    }
 
-    // 3. find the length of the prefix
-    let prefix_size = if prefix.len() > 0 { find_prefix_size(&generated_key, &prefix, block_size) } else { 0 };
-    assert_eq!(prefix_size, prefix.len());
+    fn fails_with<T>(result: Result<T>, expected: &str) {
+        match result {
+            Err(e) => assert_eq!(format!("{:?}", e), expected),
+            Ok(_) => panic!("expected {}", expected),
+        }
+    }
 
-    generate_next_byte(&generated_key, block_size, &prefix, prefix_size)
+    #[test]
+    fn constructors_reject_bad_lengths() {
+        fails_with(Aes::new(&[0; 15]), "InvalidKeyLength(15)");
+        fails_with(OpensslCipher::aes(&[0; 20]), "InvalidKeyLength(20)");
+        fails_with(OpensslCipher::new(Cipher::des_ede3(), &[0; 16]), "InvalidKeyLength(16)");
+
+        let cipher = Aes::new(b"YELLOW SUBMARINE").unwrap();
+        fails_with(Cbc::new(&cipher, &[0; 8]), "InvalidIvLength(8)");
+        fails_with(Cbc::new(&cipher, &[0; 16]).unwrap().encrypt_blocks(&mut [0; 20]), "NotBlockAligned(20)");
+        fails_with(CounterBlock::new(&[], 0, 17, Endian::Big), "InvalidCounterWidth(17)");
+        fails_with(CounterBlock::new(&[0; 16], 0, 0, Endian::Big), "InvalidCounterWidth(0)");
+        fails_with(CounterBlock::nist(&[0; 4], 5), "InvalidCounterWidth(5)");
+        fails_with(Ctr::with_counter_block(&cipher, CounterBlock::nist(&[0; 8], 4).unwrap()), "InvalidIvLength(8)");
+        fails_with(Ctr::new(OpensslCipher::new(Cipher::des_ede3(), &[0; 24]).unwrap(), 0), "InvalidIvLength(16)");
+
+        let mut cipher_text = vec![0; 10];
+        fails_with(Ctr::new(&cipher, 0).unwrap().edit(&mut cipher_text, 11, b"x"), "OutOfBounds(11)");
+
+        fails_with(ecb(&cipher, &[0; 17], Mode::Encrypt), "NotBlockAligned(17)");
+        fails_with(cbc(&cipher, &[0; 16], &[0; 17], Mode::Decrypt), "NotBlockAligned(17)");
+        fails_with(cbc(&cipher, &[0; 15], &[0; 16], Mode::Encrypt), "InvalidIvLength(15)");
+        fails_with(cfb(&cipher, &[0; 17], &[0; 5], Mode::Encrypt), "InvalidIvLength(17)");
+        fails_with(ofb(&cipher, &[], &[0; 5]), "InvalidIvLength(0)");
+
+        // a CTR nonce is 8 bytes, nothing gets cut off or filled in
+        fails_with(crypt(&cipher, CipherMode::Ctr, Some(&[0; 16]), &[0; 5], Mode::Encrypt), "InvalidIvLength(16)");
+        fails_with(crypt(&cipher, CipherMode::Ctr, Some(&[0; 12]), &[0; 5], Mode::Encrypt), "InvalidIvLength(12)");
+        assert_eq!(crypt(&cipher, CipherMode::Ctr, None, &[0; 5], Mode::Encrypt).unwrap(), ctr(&cipher, 0, &[0; 5]).unwrap());
+    }
 }
-
@@ -0,0 +1,510 @@
+// Attacks on oracles that encrypt attacker controlled input in ECB mode. The oracle is any
+// function from input to cipher text, whatever it puts around the input stays unknown.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use conversions::Padding;
+use crypter::CipherMode;
+use error::{Error, Result};
+use parallel::Parallel;
+
+
+// the largest block size looked for
+const MAX_BLOCK_SIZE: usize = 64;
+
+// alignment is retried this many times per block of block size, a prefix that changes length
+// on every call lines up about once per block size calls
+const ALIGN_ROUNDS: usize = 32;
+
+// zeros sent by detect_mode, at least seven whole blocks of any size wherever they start
+const PROBE_LEN: usize = 8 * MAX_BLOCK_SIZE - 1;
+
+/// What an ECB oracle puts around the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub block_size: usize,
+    /// None when the prefix changes length from call to call
+    pub prefix_len: Option<usize>,
+    pub suffix_len: usize,
+}
+
+// the start of the first run of three equal blocks
+fn repeated_run(cipher_text: &[u8], block_size: usize) -> Option<usize> {
+    let blocks: Vec<&[u8]> = cipher_text.chunks(block_size).collect();
+    blocks.windows(3)
+        .position(|w| w[0] == w[1] && w[1] == w[2])
+        .map(|idx| idx * block_size)
+}
+
+/// Blocks of cipher_text that are the same as an earlier block
+pub fn repeated_blocks(cipher_text: &[u8], block_size: usize) -> usize {
+    let mut seen = HashSet::new();
+    cipher_text.chunks(block_size).filter(|block| !seen.insert(*block)).count()
+}
+
+/// The share of blocks repeating an earlier one. ECB encryptions of anything with structure
+/// score above 0, other modes practically never do.
+pub fn ecb_score(cipher_text: &[u8], block_size: usize) -> f32 {
+    let blocks = cipher_text.len().div_ceil(block_size);
+    if blocks < 2 {
+        return 0.0;
+    }
+    repeated_blocks(cipher_text, block_size) as f32 / (blocks - 1) as f32
+}
+
+/// The index and score of the cipher text most likely to be ECB, None when no block repeats
+/// in any of them
+pub fn find_ecb<'a, I>(cipher_texts: I, block_size: usize) -> Option<(usize, f32)>
+        where I: IntoIterator<Item = &'a [u8]> {
+    cipher_texts.into_iter()
+        .map(|cipher_text| ecb_score(cipher_text, block_size))
+        .enumerate()
+        .filter(|&(_, score)| score > 0.0)
+        .fold(None, |best: Option<(usize, f32)>, (idx, score)| match best {
+            Some((_, top)) if top >= score => best,
+            _ => Some((idx, score)),
+        })
+}
+
+/// A mode and how sure the detector is of it, from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub mode: CipherMode,
+    pub confidence: f32,
+}
+
+// the share of the probe's repeats that came back at block_size
+fn probe_repeats(cipher_text: &[u8], block_size: usize) -> f32 {
+    let expected = (PROBE_LEN - (block_size - 1)) / block_size - 1;
+    (repeated_blocks(cipher_text, block_size) as f32 / expected as f32).min(1.0)
+}
+
+/// Tells ECB from CBC with a single call, so the oracle may pick a new mode every time.
+///
+/// A run of zeros encrypts to a run of equal blocks under ECB. The confidence is the share
+/// of the expected repeats that showed up, or didn't for CBC. Any other mode that hides
+/// repeated plain text is reported as CBC.
+pub fn detect_mode<O: Fn(&[u8]) -> Vec<u8> + ?Sized>(oracle: &O) -> Detection {
+    let cipher_text = oracle(&[0; PROBE_LEN]);
+    let sizes = (2..=MAX_BLOCK_SIZE).filter(|&size| cipher_text.len().is_multiple_of(size));
+
+    match sizes.clone().find(|&size| repeated_run(&cipher_text, size).is_some()) {
+        Some(size) => Detection { mode: CipherMode::Ecb, confidence: probe_repeats(&cipher_text, size) },
+        None => {
+            let closest = sizes.map(|size| probe_repeats(&cipher_text, size)).fold(0.0, f32::max);
+            Detection { mode: CipherMode::Cbc, confidence: 1.0 - closest }
+        }
+    }
+}
+
+// Lines input up on a block boundary behind two marker blocks whose encryptions are known.
+// The two markers only show up next to each other when the prefix and the padding in front
+// add up to whole blocks, so whatever follows them is the encryption of the input.
+struct Aligner<'a, O: ?Sized + 'a> {
+    oracle: &'a O,
+    parallel: &'a Parallel,
+    block_size: usize,
+    marker: Vec<u8>,
+    encrypted_marker: Vec<u8>,
+    // the padding that lined up last, for a fixed prefix it always does
+    pad: AtomicUsize,
+}
+
+impl<'a, O: Fn(&[u8]) -> Vec<u8> + Sync + ?Sized> Aligner<'a, O> {
+    fn new(parallel: &'a Parallel, oracle: &'a O) -> Result<Aligner<'a, O>> {
+        let query = |input: &[u8]| parallel.query(|| oracle(input));
+
+        // enough zeros for three whole blocks of any size wherever they start, the smallest
+        // size that repeats is the block size
+        let zeros = query(&[0; 4 * MAX_BLOCK_SIZE - 1]);
+        let (block_size, start) = (2..=MAX_BLOCK_SIZE)
+            .filter(|&size| zeros.len().is_multiple_of(size))
+            .find_map(|size| repeated_run(&zeros, size).map(|start| (size, start)))
+            .ok_or(Error::NotEcb)?;
+
+        let ones = query(&vec![0xff; 4 * block_size - 1]);
+        let ones_start = repeated_run(&ones, block_size).ok_or(Error::NotEcb)?;
+
+        let mut marker = vec![0; block_size];
+        marker.extend(vec![0xff; block_size]);
+        let mut encrypted_marker = zeros[start..start + block_size].to_vec();
+        encrypted_marker.extend_from_slice(&ones[ones_start..ones_start + block_size]);
+
+        Ok(Aligner { oracle, parallel, block_size, marker, encrypted_marker, pad: AtomicUsize::new(0) })
+    }
+
+    // a single call with pad bytes in front of the marker, the block index right after the
+    // marker and the cipher text when it lined up
+    fn attempt(&self, pad: usize, input: &[u8]) -> Option<(usize, Vec<u8>)> {
+        let mut plain_text = vec![0x55; pad];
+        plain_text.extend_from_slice(&self.marker);
+        plain_text.extend_from_slice(input);
+
+        let cipher_text = self.parallel.query(|| (self.oracle)(&plain_text));
+        let marker_len = self.encrypted_marker.len();
+        cipher_text.chunks(self.block_size)
+            .position(|block| block == &self.encrypted_marker[..self.block_size])
+            .map(|idx| idx + 2)
+            .filter(|&next| cipher_text.len() >= next * self.block_size
+                    && cipher_text[next * self.block_size - marker_len..next * self.block_size] == self.encrypted_marker[..])
+            .map(|next| (next, cipher_text))
+    }
+
+    // the block index of the input and the whole cipher text
+    fn aligned(&self, input: &[u8]) -> Result<(usize, Vec<u8>)> {
+        let first = self.pad.load(Ordering::Relaxed);
+        for round in 0..ALIGN_ROUNDS * self.block_size {
+            let pad = (first + round) % self.block_size;
+            if let Some(found) = self.attempt(pad, input) {
+                self.pad.store(pad, Ordering::Relaxed);
+                return Ok(found);
+            }
+        }
+        Err(Error::NotEcb)
+    }
+
+    // the encryption of input followed by the suffix
+    fn encrypt(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let (next, mut cipher_text) = self.aligned(input)?;
+        Ok(cipher_text.split_off(next * self.block_size))
+    }
+
+    fn prefix_len(&self) -> Result<Option<usize>> {
+        let (next, _) = self.aligned(&[])?;
+        let pad = self.pad.load(Ordering::Relaxed);
+
+        // the same padding has to line up at the same block every time
+        for _ in 0..4 {
+            match self.attempt(pad, &[]) {
+                Some((again, _)) if again == next => (),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some((next - 2) * self.block_size - pad))
+    }
+
+    // PKCS#7 style padding always adds something, the output grows a block as soon as the
+    // input and the suffix fill the last one
+    fn suffix_len(&self) -> Result<usize> {
+        let empty = self.encrypt(&[])?.len();
+        for n in 1..=self.block_size {
+            let grown = self.encrypt(&vec![0; n])?.len();
+            if grown > empty {
+                return Ok(empty - n);
+            }
+        }
+        Ok(empty)
+    }
+}
+
+/// Block size, prefix and suffix length of an ECB oracle
+pub fn layout<O>(parallel: &Parallel, oracle: &O) -> Result<Layout>
+        where O: Fn(&[u8]) -> Vec<u8> + Sync + ?Sized {
+    let aligner = Aligner::new(parallel, oracle)?;
+    Ok(Layout {
+        block_size: aligner.block_size,
+        prefix_len: aligner.prefix_len()?,
+        suffix_len: aligner.suffix_len()?,
+    })
+}
+
+/// Decrypts the unknown suffix an ECB oracle appends to the input, one byte at a time.
+///
+/// Each byte is pushed to the end of a block behind known bytes, then compared against the
+/// encryptions of that block with every possible last byte. The 256 candidate blocks are
+/// sent in batches, one per thread of the driver.
+pub fn decrypt_suffix<O>(parallel: &Parallel, oracle: &O) -> Result<Vec<u8>>
+        where O: Fn(&[u8]) -> Vec<u8> + Sync + ?Sized {
+    let aligner = Aligner::new(parallel, oracle)?;
+    let block_size = aligner.block_size;
+    let suffix_len = aligner.suffix_len()?;
+
+    // the suffix behind every amount of filler, filler.len() == block_size - 1 - i % block_size
+    // puts byte i at the end of block i / block_size
+    let fillers: Vec<usize> = (0..block_size).collect();
+    let shifted = parallel.map(&fillers, |&n| aligner.encrypt(&vec![0; n]))
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+    let guesses: Vec<u8> = (0..=255).collect();
+    let batches: Vec<&[u8]> = guesses.chunks(256_usize.div_ceil(parallel.limit())).collect();
+
+    let mut known = vec!();
+    for i in 0..suffix_len {
+        let filler = block_size - 1 - i % block_size;
+        let block = i / block_size;
+        let target = &shifted[filler][block * block_size..(block + 1) * block_size];
+
+        let mut window = vec![0; filler];
+        window.extend_from_slice(&known);
+        let window = &window[window.len() - (block_size - 1)..];
+
+        let found = parallel.map(&batches, |batch| -> Result<Option<u8>> {
+            let candidates: Vec<u8> = batch.iter()
+                .flat_map(|&guess| window.iter().cloned().chain(Some(guess)))
+                .collect();
+            let cipher_text = aligner.encrypt(&candidates)?;
+            Ok(batch.iter()
+               .zip(cipher_text.chunks(block_size))
+               .find(|&(_, encrypted)| encrypted == target)
+               .map(|(&guess, _)| guess))
+        });
+
+        let mut next = None;
+        for guess in found {
+            next = next.or(guess?);
+        }
+        known.push(next.ok_or(Error::OracleExhausted)?);
+    }
+    Ok(known)
+}
+
+/// Cut and paste forging against a record format that ECB encrypts `prefix + input + suffix`
+/// with a known prefix and suffix, the shape of most cookie and token formats. Any block of
+/// a target record that some chosen input lines up on a block boundary can be cut out of
+/// the oracle's output and pasted into a forgery.
+pub struct CutAndPaste<'a, O: ?Sized + 'a, P: ?Sized + 'a> {
+    oracle: &'a O,
+    padding: &'a P,
+    block_size: usize,
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+    forbidden: Vec<u8>,
+}
+
+impl<'a, O, P> CutAndPaste<'a, O, P> where O: Fn(&[u8]) -> Vec<u8> + ?Sized, P: Padding + ?Sized {
+    pub fn new(oracle: &'a O, padding: &'a P, block_size: usize, prefix: &[u8], suffix: &[u8]) -> CutAndPaste<'a, O, P> {
+        CutAndPaste {
+            oracle, padding, block_size,
+            prefix: prefix.to_vec(),
+            suffix: suffix.to_vec(),
+            forbidden: vec!(),
+        }
+    }
+
+    /// Bytes the format strips or escapes from the input, they are never sent
+    pub fn forbidding(mut self, bytes: &[u8]) -> Self {
+        self.forbidden.extend_from_slice(bytes);
+        self
+    }
+
+    fn allowed(&self, byte: u8) -> bool {
+        !self.forbidden.contains(&byte)
+    }
+
+    // the record for input_len bytes of input, None where the input goes. None altogether
+    // for a block size the padding can't handle.
+    fn record(&self, input_len: usize) -> Option<Vec<Option<u8>>> {
+        let mut fixed = self.prefix.clone();
+        fixed.extend(vec![0; input_len]);
+        fixed.extend_from_slice(&self.suffix);
+        self.padding.pad(&mut fixed, self.block_size).ok()?;
+
+        let input = self.prefix.len()..self.prefix.len() + input_len;
+        Some(fixed.into_iter()
+            .enumerate()
+            .map(|(i, byte)| if input.contains(&i) { None } else { Some(byte) })
+            .collect())
+    }
+
+    /// A chosen input that makes the oracle encrypt block on its own, and the index of the
+    /// block that holds it in the output. The block is either all input or lines up input
+    /// with the fixed parts of the record, including the padding at the end. None when there
+    /// is no such input, or block isn't a whole block.
+    pub fn input_for(&self, block: &[u8]) -> Option<(Vec<u8>, usize)> {
+        // forging works a block at a time
+        if block.len() != self.block_size {
+            return None;
+        }
+        let filler = (b'A'..=b'Z').chain(0..=255).find(|&b| self.allowed(b))?;
+
+        // past two blocks of input every alignment has been seen
+        for input_len in 0..2 * self.block_size {
+            let record = self.record(input_len)?;
+            for (idx, part) in record.chunks(self.block_size).enumerate() {
+                let fits = part.iter().zip(block.iter()).all(|(&fixed, &wanted)| match fixed {
+                    Some(byte) => byte == wanted,
+                    None => self.allowed(wanted),
+                });
+                if !fits {
+                    continue;
+                }
+
+                let mut input = vec![filler; input_len];
+                for (offset, &wanted) in block.iter().enumerate() {
+                    let at = idx * self.block_size + offset;
+                    if at >= self.prefix.len() && at < self.prefix.len() + input_len {
+                        input[at - self.prefix.len()] = wanted;
+                    }
+                }
+                return Some((input, idx));
+            }
+        }
+        None
+    }
+
+    /// The encryption of a single block, None if the oracle's output is too short to hold it
+    pub fn encrypt_block(&self, block: &[u8]) -> Option<Vec<u8>> {
+        let (input, idx) = self.input_for(block)?;
+        let cipher_text = (self.oracle)(&input);
+        cipher_text.get(idx * self.block_size..(idx + 1) * self.block_size).map(|c| c.to_vec())
+    }
+
+    /// Pads record and pieces its encryption together block by block
+    pub fn forge(&self, record: &[u8]) -> Result<Vec<u8>> {
+        let mut padded = record.to_vec();
+        self.padding.pad(&mut padded, self.block_size)?;
+
+        let mut forged = vec!();
+        for (idx, block) in padded.chunks(self.block_size).enumerate() {
+            forged.extend(self.encrypt_block(block).ok_or(Error::UnforgeableBlock(idx))?);
+        }
+        Ok(forged)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use conversions::{pad_pkcs7, Pkcs7};
+    use crypter::{ecb, cbc, random_aes_key, random_bytes, OpensslCipher, BlockCipher};
+    use openssl::symm::{Cipher, Mode};
+    use rand::{thread_rng, Rng};
+
+    const SUFFIX: &[u8] = b"Rollin' in my 5.0\nWith my rag-top down so my hair can blow\n";
+
+    fn ecb_oracle<C: BlockCipher>(cipher: C, prefix: Vec<u8>) -> impl Fn(&[u8]) -> Vec<u8> {
+        move |input| {
+            let mut plain_text = [&prefix[..], input, SUFFIX].concat();
+            pad_pkcs7(&mut plain_text, cipher.block_size()).unwrap();
+            ecb(&cipher, &plain_text, Mode::Encrypt).unwrap()
+        }
+    }
+
+    #[test]
+    fn fixed_prefix() {
+        let parallel = Parallel::new(4);
+        for &prefix_len in &[0, 5, 16, 37] {
+            let oracle = ecb_oracle(Aes::new(&random_aes_key()).unwrap(), random_bytes(prefix_len, prefix_len));
+            let expected = Layout { block_size: 16, prefix_len: Some(prefix_len), suffix_len: SUFFIX.len() };
+            assert_eq!(layout(&parallel, &oracle).unwrap(), expected);
+            assert_eq!(decrypt_suffix(&parallel, &oracle).unwrap(), SUFFIX);
+        }
+    }
+
+    #[test]
+    fn eight_byte_blocks() {
+        let cipher = OpensslCipher::new(Cipher::des_ede3(), &random_bytes(24, 24)).unwrap();
+        let oracle = ecb_oracle(&cipher, b"prefix".to_vec());
+        let parallel = Parallel::new(2);
+        assert_eq!(layout(&parallel, &oracle).unwrap().block_size, 8);
+        assert_eq!(decrypt_suffix(&parallel, &oracle).unwrap(), SUFFIX);
+    }
+
+    #[test]
+    fn random_prefix_per_call() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = |input: &[u8]| {
+            let prefix_len = thread_rng().gen_range(0, 48);
+            ecb_oracle(&cipher, random_bytes(prefix_len, prefix_len))(input)
+        };
+
+        let parallel = Parallel::new(8);
+        let found = layout(&parallel, &oracle).unwrap();
+        assert_eq!((found.block_size, found.prefix_len, found.suffix_len), (16, None, SUFFIX.len()));
+        assert_eq!(decrypt_suffix(&parallel, &oracle).unwrap(), SUFFIX);
+    }
+
+    #[test]
+    fn classify_modes() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let ecb_detected = detect_mode(&ecb_oracle(&cipher, random_bytes(5, 10)));
+        assert_eq!(ecb_detected.mode, CipherMode::Ecb);
+        assert!(ecb_detected.confidence > 0.99);
+
+        let cbc_oracle = |input: &[u8]| {
+            let mut plain_text = [input, SUFFIX].concat();
+            pad_pkcs7(&mut plain_text, 16).unwrap();
+            cbc(&cipher, &random_bytes(16, 16), &plain_text, Mode::Encrypt).unwrap()
+        };
+        let cbc_detected = detect_mode(&cbc_oracle);
+        assert_eq!(cbc_detected.mode, CipherMode::Cbc);
+        assert!(cbc_detected.confidence > 0.9);
+
+        let des = |input: &[u8]| ecb_oracle(OpensslCipher::new(Cipher::des_ede3(), &[7; 24]).unwrap(), vec!())(input);
+        assert_eq!(detect_mode(&des).mode, CipherMode::Ecb);
+    }
+
+    #[test]
+    fn cipher_text_only() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let mut structured = b"YELLOW SUBMARINE".repeat(3);
+        structured.extend_from_slice(SUFFIX);
+        pad_pkcs7(&mut structured, 16).unwrap();
+
+        let cipher_texts = [
+            cbc(&cipher, &[0; 16], &structured, Mode::Encrypt).unwrap(),
+            ecb(&cipher, &structured, Mode::Encrypt).unwrap(),
+            random_bytes(64, 64),
+        ];
+        assert_eq!(repeated_blocks(&cipher_texts[1], 16), 2);
+        assert_eq!(ecb_score(&cipher_texts[0], 16), 0.0);
+        assert_eq!(find_ecb(cipher_texts.iter().map(|c| &c[..]), 16), Some((1, 2.0 / 6.0)));
+        assert_eq!(find_ecb(cipher_texts[2..].iter().map(|c| &c[..]), 16), None);
+    }
+
+    #[test]
+    fn cut_and_paste_token() {
+        // uid=7;name=<input>;admin=0, with the separators escaped out of the name
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = |name: &[u8]| {
+            let escaped: Vec<u8> = name.iter().cloned().filter(|&b| b != b';' && b != b'=').collect();
+            let mut plain_text = [&b"uid=7;name="[..], &escaped, b";admin=0"].concat();
+            pad_pkcs7(&mut plain_text, 16).unwrap();
+            ecb(&cipher, &plain_text, Mode::Encrypt).unwrap()
+        };
+        let forger = CutAndPaste::new(&oracle, &Pkcs7, 16, b"uid=7;name=", b";admin=0").forbidding(b";=");
+
+        // a block of nothing but input, behind enough filler to finish the prefix's block
+        let mut admin = b"1".to_vec();
+        admin.extend(vec![15; 15]);
+        let (input, idx) = forger.input_for(&admin).unwrap();
+        assert_eq!((&input[..5], &input[5..], idx), (&b"AAAAA"[..], &admin[..], 1));
+
+        // the name is picked so the fixed ";admin=" ends a block
+        let record = b"uid=7;name=mallory1234567;admin=1";
+        let forged = forger.forge(record).unwrap();
+        let mut decrypted = ecb(&cipher, &forged, Mode::Decrypt).unwrap();
+        Pkcs7.unpad(&mut decrypted, 16).unwrap();
+        assert_eq!(decrypted, &record[..]);
+
+        // without the fixed parts lining up there is nothing to cut
+        match forger.forge(b"uid=0;name=mallory;admin=1") {
+            Err(Error::UnforgeableBlock(0)) => (),
+            other => panic!("expected UnforgeableBlock, got {:?}", other),
+        }
+        assert!(forger.input_for(b"short").is_none());
+
+        // an oracle that cuts its output short of what the layout promises
+        let truncating = |name: &[u8]| oracle(name)[..16].to_vec();
+        let short = CutAndPaste::new(&truncating, &Pkcs7, 16, b"uid=7;name=", b";admin=0").forbidding(b";=");
+        assert!(short.encrypt_block(&admin).is_none());
+        assert!(short.forge(record).is_err());
+    }
+
+    #[test]
+    fn not_ecb() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = |input: &[u8]| {
+            let mut plain_text = input.to_vec();
+            pad_pkcs7(&mut plain_text, 16).unwrap();
+            cbc(&cipher, &[0; 16], &plain_text, Mode::Encrypt).unwrap()
+        };
+        match decrypt_suffix(&Parallel::new(1), &oracle) {
+            Err(Error::NotEcb) => (),
+            other => panic!("expected NotEcb, got {:?}", other),
+        }
+    }
+}
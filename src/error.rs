@@ -0,0 +1,172 @@
+// the single error type returned by every fallible public function in the crate
+
+use std::error;
+use std::fmt;
+use std::result;
+use std::string::FromUtf8Error;
+use openssl::error::ErrorStack;
+
+
+#[derive(Debug)]
+pub enum Error {
+    /// a character outside of 0-9 and a-f
+    InvalidHexChar(char),
+    /// hex strings encode each byte as a pair of characters
+    OddHexLength,
+    /// a character outside of the base64 alphabet
+    InvalidBase64Char(char),
+    /// base64 input has to come in groups of four characters
+    InvalidBase64Length,
+    InvalidBase32Char(char),
+    /// a final base32 group that can't be the encoding of whole bytes
+    InvalidBase32Length,
+    InvalidBase58Char(char),
+    InvalidBase85Char(char),
+    /// a final group of a single character, or a Z85 input that isn't whole groups
+    InvalidBase85Length,
+    /// a group of five characters that decodes to more than 32 bits
+    Base85Overflow,
+    /// the offset of a `%` that isn't followed by two hex digits
+    InvalidPercentEscape(usize),
+    InvalidKeyLength(usize),
+    /// a round count the cipher can't run with
+    InvalidRounds(usize),
+    MissingIv,
+    InvalidIvLength(usize),
+    /// an authentication tag length the mode doesn't allow
+    InvalidTagLength(usize),
+    /// a tag length in bits an attack can't work with
+    InvalidTagBits(usize),
+    /// a CTR counter that is wider than 16 bytes or its counter block, or empty
+    InvalidCounterWidth(usize),
+    /// a block cipher with a block size the mode can't use
+    InvalidBlockSize(usize),
+    /// the input length, which is not a multiple of the block size
+    NotBlockAligned(usize),
+    /// the length of an input too short for the attack
+    TooShort(usize),
+    /// the number of messages given to an attack that needs more of them
+    TooFewMessages(usize),
+    /// a `key=value` item without the `=`
+    MalformedKeyValue(String),
+    /// the value has no multiplicative inverse for the given modulus
+    NoInverse,
+    Padding(PaddingError),
+    /// an oracle accepted none of the 256 guesses for a byte
+    OracleExhausted,
+    /// an oracle took tampered input without giving anything away
+    OracleSilent,
+    /// an oracle whose output never showed the repeated blocks of ECB
+    NotEcb,
+    /// an oracle whose answers contradict each other
+    OracleInconsistent,
+    /// a padding scheme that gives a padding oracle too little away to attack
+    UnsupportedPadding,
+    /// the number of queries an attack used up without getting anywhere
+    QueryBudgetExhausted(usize),
+    /// the index of a block that no chosen input can make the oracle encrypt
+    UnforgeableBlock(usize),
+    /// the offset of plain text that flipping cipher text can't rewrite
+    Unflippable(usize),
+    /// the lengths of two inputs that have to match
+    LengthMismatch(usize, usize),
+    /// a filter that leaves none of the 256 byte values
+    EveryByteForbidden,
+    /// an offset past the end of the input
+    OutOfBounds(usize),
+    /// the offset of a key stream byte that is already known to be something else
+    KeyStreamConflict(usize),
+    Utf8(FromUtf8Error),
+    Openssl(ErrorStack),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidHexChar(c) => write!(f, "invalid hex character {:?}", c),
+            Error::OddHexLength => write!(f, "hex input has an odd number of characters"),
+            Error::InvalidBase64Char(c) => write!(f, "invalid base64 character {:?}", c),
+            Error::InvalidBase64Length => write!(f, "base64 input is improperly padded"),
+            Error::InvalidBase32Char(c) => write!(f, "invalid base32 character {:?}", c),
+            Error::InvalidBase32Length => write!(f, "base32 input is improperly padded"),
+            Error::InvalidBase58Char(c) => write!(f, "invalid base58 character {:?}", c),
+            Error::InvalidBase85Char(c) => write!(f, "invalid base85 character {:?}", c),
+            Error::InvalidBase85Length => write!(f, "base85 input has an incomplete group"),
+            Error::Base85Overflow => write!(f, "base85 group does not fit in 32 bits"),
+            Error::InvalidPercentEscape(offset) => write!(f, "invalid percent escape at offset {}", offset),
+            Error::InvalidKeyLength(len) => write!(f, "invalid key length of {} bytes", len),
+            Error::InvalidRounds(rounds) => write!(f, "invalid number of rounds {}", rounds),
+            Error::MissingIv => write!(f, "the mode of operation requires an IV"),
+            Error::InvalidIvLength(len) => write!(f, "invalid IV length of {} bytes", len),
+            Error::InvalidTagLength(len) => write!(f, "invalid tag length of {} bytes", len),
+            Error::InvalidTagBits(bits) => write!(f, "invalid tag length of {} bits", bits),
+            Error::InvalidCounterWidth(width) => write!(f, "invalid counter width of {} bytes", width),
+            Error::InvalidBlockSize(len) => write!(f, "invalid block size of {} bytes", len),
+            Error::NotBlockAligned(len) => write!(f, "input of {} bytes is not block aligned", len),
+            Error::TooShort(len) => write!(f, "input of {} bytes is too short", len),
+            Error::TooFewMessages(count) => write!(f, "{} messages are too few", count),
+            Error::MalformedKeyValue(ref item) => write!(f, "malformed key value pair {:?}", item),
+            Error::NoInverse => write!(f, "no modular inverse exists"),
+            Error::Padding(ref e) => e.fmt(f),
+            Error::OracleExhausted => write!(f, "the oracle rejected every guess"),
+            Error::OracleSilent => write!(f, "the oracle leaked nothing"),
+            Error::NotEcb => write!(f, "the oracle does not encrypt in ECB mode"),
+            Error::UnsupportedPadding => write!(f, "the padding oracle can not be attacked under this padding"),
+            Error::OracleInconsistent => write!(f, "the oracle contradicted itself"),
+            Error::QueryBudgetExhausted(queries) => write!(f, "gave up after {} queries", queries),
+            Error::UnforgeableBlock(idx) => write!(f, "block {} can not be forged", idx),
+            Error::Unflippable(offset) => write!(f, "plain text at offset {} can not be flipped", offset),
+            Error::LengthMismatch(a, b) => write!(f, "lengths of {} and {} bytes differ", a, b),
+            Error::EveryByteForbidden => write!(f, "every byte value is forbidden"),
+            Error::OutOfBounds(offset) => write!(f, "offset {} is past the end of the input", offset),
+            Error::KeyStreamConflict(offset) => write!(f, "key stream byte {} is already locked to another value", offset),
+            Error::Utf8(ref e) => write!(f, "invalid utf-8: {}", e),
+            Error::Openssl(ref e) => write!(f, "openssl error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Padding(ref e) => Some(e),
+            Error::Utf8(ref e) => Some(e),
+            Error::Openssl(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PaddingError> for Error {
+    fn from(e: PaddingError) -> Error {
+        Error::Padding(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Error {
+        Error::Utf8(e)
+    }
+}
+
+impl From<ErrorStack> for Error {
+    fn from(e: ErrorStack) -> Error {
+        Error::Openssl(e)
+    }
+}
+
+
+/// Padding that doesn't check out. Deliberately carries no detail about what was wrong
+/// with it, anything more is a free hint for a padding oracle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaddingError;
+
+impl fmt::Display for PaddingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid padding")
+    }
+}
+
+impl error::Error for PaddingError {}
@@ -0,0 +1,273 @@
+// Galois/Counter Mode (NIST SP 800-38D) over any 128 bit crypter::BlockCipher.
+// GHASH is exposed on its own so nonce reuse and forgery attacks can be built on the same code.
+
+use crypter::{BlockCipher, Ctr, CounterBlock};
+use error::{Error, Result};
+use gf128::{self, Element};
+use measure::constant_time_eq;
+
+pub const TAG_SIZE: usize = 16;
+/// the shortest tag `Gcm::with_tag_len` takes, SP 800-38D's 96 bits
+pub const MIN_TAG_SIZE: usize = 12;
+
+/// GHASH keyed with the hash subkey H, fed block by block
+#[derive(Debug, Clone)]
+pub struct Ghash {
+    h: Element,
+    acc: Element,
+}
+
+impl Ghash {
+    pub fn new(h: Element) -> Ghash {
+        Ghash { h, acc: gf128::ZERO }
+    }
+
+    pub fn update_block(&mut self, block: &[u8]) {
+        self.acc = gf128::mul(self.acc ^ gf128::from_block(block), self.h);
+    }
+
+    // a trailing partial block is zero padded
+    pub fn update_padded(&mut self, data: &[u8]) {
+        for block in data.chunks(16) {
+            self.update_block(block);
+        }
+    }
+
+    pub fn update_lengths(&mut self, aad_len: usize, cipher_text_len: usize) {
+        let lengths = ((aad_len as u128 * 8) << 64) | (cipher_text_len as u128 * 8);
+        self.update_block(&lengths.to_be_bytes());
+    }
+
+    pub fn value(&self) -> Element {
+        self.acc
+    }
+
+    pub fn digest(&self) -> [u8; 16] {
+        gf128::to_block(self.acc)
+    }
+}
+
+/// GHASH_H(A || 0* || C || 0* || [len(A)]64 || [len(C)]64)
+pub fn ghash(h: Element, aad: &[u8], cipher_text: &[u8]) -> Element {
+    let mut g = Ghash::new(h);
+    g.update_padded(aad);
+    g.update_padded(cipher_text);
+    g.update_lengths(aad.len(), cipher_text.len());
+    g.value()
+}
+
+/// GCM with a tag length fixed when it's built. Tags of any other length are rejected, so
+/// a forger can't pick a short one and guess it.
+pub struct Gcm<C: BlockCipher> {
+    cipher: C,
+    h: Element,
+    tag_len: usize,
+}
+
+impl<C: BlockCipher> Gcm<C> {
+    /// full 16 byte tags, the cipher has to have 128 bit blocks
+    pub fn new(cipher: C) -> Result<Gcm<C>> {
+        if cipher.block_size() != 16 {
+            return Err(Error::InvalidBlockSize(cipher.block_size()));
+        }
+        let mut h = [0u8; 16];
+        cipher.encrypt_block(&mut h);
+        Ok(Gcm { cipher, h: gf128::from_block(&h), tag_len: TAG_SIZE })
+    }
+
+    /// Tags cut down to tag_len bytes, MIN_TAG_SIZE up to TAG_SIZE
+    pub fn with_tag_len(cipher: C, tag_len: usize) -> Result<Gcm<C>> {
+        if !(MIN_TAG_SIZE..=TAG_SIZE).contains(&tag_len) {
+            return Err(Error::InvalidTagLength(tag_len));
+        }
+        Self::with_short_tag(cipher, tag_len)
+    }
+
+    /// Tags of any length from a single byte up. Short tags are trivially forgeable, this is
+    /// for stand-ins of broken services in attacks like gcm_attack's.
+    pub fn with_short_tag(cipher: C, tag_len: usize) -> Result<Gcm<C>> {
+        if tag_len == 0 || tag_len > TAG_SIZE {
+            return Err(Error::InvalidTagLength(tag_len));
+        }
+        Ok(Gcm { tag_len, ..Self::new(cipher)? })
+    }
+
+    pub fn tag_len(&self) -> usize {
+        self.tag_len
+    }
+
+    /// the hash subkey E_K(0^128)
+    pub fn hash_key(&self) -> Element {
+        self.h
+    }
+
+    // 96 bit nonces are used directly, anything else goes through GHASH
+    fn pre_counter_block(&self, nonce: &[u8]) -> [u8; 16] {
+        if nonce.len() == 12 {
+            let mut j0 = [0u8; 16];
+            j0[..12].copy_from_slice(nonce);
+            j0[15] = 1;
+            j0
+        } else {
+            let mut g = Ghash::new(self.h);
+            g.update_padded(nonce);
+            g.update_lengths(0, nonce.len());
+            g.digest()
+        }
+    }
+
+    fn key_stream(&self, j0: &[u8; 16], data: &mut [u8]) {
+        // a 4 byte counter in a 16 byte block for a cipher new made sure has 16 byte blocks
+        let counter_block = CounterBlock::nist(j0, 4).expect("counter fits the block");
+        let mut ctr = Ctr::with_counter_block(&self.cipher, counter_block).expect("block sizes match");
+        ctr.seek(16);
+        ctr.apply_key_stream(data);
+    }
+
+    fn tag(&self, j0: &[u8; 16], aad: &[u8], cipher_text: &[u8]) -> [u8; 16] {
+        let mut mask = *j0;
+        self.cipher.encrypt_block(&mut mask);
+        gf128::to_block(ghash(self.h, aad, cipher_text) ^ gf128::from_block(&mask))
+    }
+
+    /// returns the cipher text and the tag, tag_len bytes long
+    pub fn encrypt(&self, nonce: &[u8], aad: &[u8], plain_text: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let j0 = self.pre_counter_block(nonce);
+        let mut cipher_text = plain_text.to_vec();
+        self.key_stream(&j0, &mut cipher_text);
+        let tag = self.tag(&j0, aad, &cipher_text);
+        (cipher_text, tag[..self.tag_len].to_vec())
+    }
+
+    /// Verifies the tag before decrypting. It has to be exactly tag_len bytes long and match
+    /// the leading bytes of the full tag.
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], cipher_text: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+        if tag.len() != self.tag_len {
+            return None;
+        }
+
+        let j0 = self.pre_counter_block(nonce);
+        let expected = self.tag(&j0, aad, cipher_text);
+        if !constant_time_eq(&expected[..self.tag_len], tag) {
+            return None;
+        }
+
+        let mut plain_text = cipher_text.to_vec();
+        self.key_stream(&j0, &mut plain_text);
+        Some(plain_text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use conversions::{string_to_hex, hex_to_string};
+    use crypter::{random_bytes, OpensslCipher};
+    use openssl::symm::{encrypt_aead, Cipher};
+
+    const KEY: &str = "feffe9928665731c6d6a8f9467308308";
+    const PLAIN: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                         1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255";
+    const CIPHER: &str = "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                          21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985";
+
+    // test cases from "The Galois/Counter Mode of Operation", McGrew and Viega
+    #[test]
+    fn zero_key_test_cases() {
+        let gcm = Gcm::new(Aes::new(&[0; 16]).unwrap()).unwrap();
+        assert_eq!(hex_to_string(&gf128::to_block(gcm.hash_key())), "66e94bd4ef8a2c3b884cfa59ca342b2e");
+
+        let (c, t) = gcm.encrypt(&[0; 12], &[], &[]);
+        assert!(c.is_empty());
+        assert_eq!(hex_to_string(&t), "58e2fccefa7e3061367f1d57a4e7455a");
+
+        let (c, t) = gcm.encrypt(&[0; 12], &[], &[0; 16]);
+        assert_eq!(hex_to_string(&c), "0388dace60b6a392f328c2b971b2fe78");
+        assert_eq!(hex_to_string(&gf128::to_block(ghash(gcm.hash_key(), &[], &c))), "f38cbb1ad69223dcc3457ae5b6b0f885");
+        assert_eq!(hex_to_string(&t), "ab6e47d42cec13bdf53a67b21257bddf");
+    }
+
+    #[test]
+    fn test_cases_3_and_4() {
+        let gcm = Gcm::new(Aes::new(&string_to_hex(KEY).unwrap()).unwrap()).unwrap();
+        let nonce = string_to_hex("cafebabefacedbaddecaf888").unwrap();
+
+        let (c, t) = gcm.encrypt(&nonce, &[], &string_to_hex(PLAIN).unwrap());
+        assert_eq!(hex_to_string(&c), CIPHER);
+        assert_eq!(hex_to_string(&t), "4d5c2af327cd64a62cf35abd2ba6fab4");
+
+        let aad = string_to_hex("feedfacedeadbeeffeedfacedeadbeefabaddad2").unwrap();
+        let plain = &string_to_hex(PLAIN).unwrap()[..60];
+        let (c, t) = gcm.encrypt(&nonce, &aad, plain);
+        assert_eq!(c, &string_to_hex(CIPHER).unwrap()[..60]);
+        assert_eq!(hex_to_string(&t), "5bc94fbc3221a5db94fae95ae7121a47");
+        assert_eq!(gcm.decrypt(&nonce, &aad, &c, &t).unwrap(), plain);
+    }
+
+    #[test]
+    fn rejects_bad_tags() {
+        let gcm = Gcm::new(Aes::new(b"YELLOW SUBMARINE").unwrap()).unwrap();
+        let (c, mut t) = gcm.encrypt(&[1; 12], b"header", b"attack at dawn");
+
+        assert_eq!(gcm.decrypt(&[1; 12], b"header", &c, &t).unwrap(), b"attack at dawn");
+        assert!(gcm.decrypt(&[1; 12], b"Header", &c, &t).is_none());
+        assert!(gcm.decrypt(&[1; 12], b"header", &c, &[]).is_none());
+        // a truncated copy of the valid tag is a one in 256 guess for a single byte
+        for len in 1..TAG_SIZE {
+            assert!(gcm.decrypt(&[1; 12], b"header", &c, &t[..len]).is_none());
+        }
+        t[15] ^= 1;
+        assert!(gcm.decrypt(&[1; 12], b"header", &c, &t).is_none());
+    }
+
+    #[test]
+    fn fixed_tag_lengths() {
+        let key = b"YELLOW SUBMARINE";
+        let (c, full) = Gcm::new(Aes::new(key).unwrap()).unwrap().encrypt(&[1; 12], &[], b"attack at dawn");
+
+        let gcm = Gcm::with_tag_len(Aes::new(key).unwrap(), 12).unwrap();
+        let (_, t) = gcm.encrypt(&[1; 12], &[], b"attack at dawn");
+        assert_eq!(t, &full[..12]);
+        assert!(gcm.decrypt(&[1; 12], &[], &c, &t).is_some());
+        assert!(gcm.decrypt(&[1; 12], &[], &c, &full).is_none());
+        assert!(gcm.decrypt(&[1; 12], &[], &c, &t[..11]).is_none());
+
+        for &len in &[0, 4, 8, 11, 17] {
+            match Gcm::with_tag_len(Aes::new(key).unwrap(), len) {
+                Err(Error::InvalidTagLength(l)) => assert_eq!(l, len),
+                _ => panic!("expected InvalidTagLength for {}", len),
+            }
+        }
+
+        match Gcm::new(OpensslCipher::new(Cipher::des_ede3(), &[7; 24]).unwrap()) {
+            Err(Error::InvalidBlockSize(8)) => (),
+            _ => panic!("expected InvalidBlockSize"),
+        }
+
+        // only when asked for explicitly
+        let short = Gcm::with_short_tag(Aes::new(key).unwrap(), 2).unwrap();
+        assert!(short.decrypt(&[1; 12], &[], &c, &full[..2]).is_some());
+        assert!(short.decrypt(&[1; 12], &[], &c, &full[..4]).is_none());
+        assert!(Gcm::with_short_tag(Aes::new(key).unwrap(), 0).is_err());
+    }
+
+    #[test]
+    fn matches_openssl() {
+        for &(key_size, nonce_size) in [(16, 12), (24, 12), (32, 12), (16, 8), (16, 60)].iter() {
+            let (key, nonce) = (random_bytes(key_size, key_size), random_bytes(nonce_size, nonce_size));
+            let (aad, plain) = (random_bytes(0, 40), random_bytes(0, 100));
+            let cipher = match key_size {
+                16 => Cipher::aes_128_gcm(),
+                24 => Cipher::aes_192_gcm(),
+                _ => Cipher::aes_256_gcm(),
+            };
+
+            let mut expected_tag = [0u8; 16];
+            let expected = encrypt_aead(cipher, &key, Some(&nonce), &aad, &plain, &mut expected_tag).unwrap();
+            let (c, t) = Gcm::new(Aes::new(&key).unwrap()).unwrap().encrypt(&nonce, &aad, &plain);
+            assert_eq!(c, expected);
+            assert_eq!(t, expected_tag);
+        }
+    }
+}
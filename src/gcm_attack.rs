@@ -0,0 +1,318 @@
+// Attacks on GCM built on gcm::Ghash and poly::Poly.
+//
+// Nonce reuse ("forbidden attack", Joux): under one nonce every tag is
+//   t = GHASH_H(A, C) + E_K(J0)
+// so the xor of two tags cancels the mask and leaves a polynomial in H with known
+// coefficients. Its roots are the candidates for the authentication key H, and with H any
+// message under that nonce can be given a valid tag.
+//
+// Short tags (Ferguson, "Authentication weaknesses in GCM"): squaring is GF(2) linear, so
+// flipping bits only in the blocks that multiply H^(2^i) changes the tag by A_d h, with A_d
+// a 128x128 bit matrix that depends linearly on the flips d. Picking d so the first rows of
+// A_d vanish leaves only a few tag bits to chance, and every accepted forgery adds the
+// remaining rows of A_d as linear equations on h until only one candidate is left.
+
+use std::cell::Cell;
+use crypter::{BlockCipher, random_bytes};
+use error::{Error, Result};
+use gcm::{ghash, Gcm};
+use gf128::{self, Element};
+use gf2::{self, BitVec};
+use poly::Poly;
+use rand::{thread_rng, Rng};
+
+
+#[derive(Debug, Clone)]
+pub struct GcmMessage {
+    pub aad: Vec<u8>,
+    pub cipher_text: Vec<u8>,
+    pub tag: [u8; 16],
+}
+
+/// GHASH as a polynomial in H: B_1 H^n + ... + B_n H, B_n being the length block
+pub fn ghash_poly(aad: &[u8], cipher_text: &[u8]) -> Poly {
+    let mut blocks: Vec<Element> = aad.chunks(16).map(gf128::from_block).collect();
+    blocks.extend(cipher_text.chunks(16).map(gf128::from_block));
+    blocks.push(((aad.len() as u128 * 8) << 64) | (cipher_text.len() as u128 * 8));
+
+    let mut coeffs = vec![gf128::ZERO];
+    coeffs.extend(blocks.iter().rev());
+    Poly::new(coeffs)
+}
+
+/// GHASH_1(H) + GHASH_2(H) + t1 + t2, which is zero at the real H
+pub fn nonce_reuse_poly(a: &GcmMessage, b: &GcmMessage) -> Poly {
+    let tags = gf128::from_block(&a.tag) ^ gf128::from_block(&b.tag);
+    ghash_poly(&a.aad, &a.cipher_text)
+        .add(&ghash_poly(&b.aad, &b.cipher_text))
+        .add(&Poly::constant(tags))
+}
+
+/// Candidates for H from messages that share a nonce. Every pair has to agree on H, so
+/// more messages narrow the list down, usually to one. Needs at least two messages.
+pub fn recover_hash_keys(messages: &[GcmMessage]) -> Result<Vec<Element>> {
+    if messages.len() < 2 {
+        return Err(Error::TooFewMessages(messages.len()));
+    }
+    let mut candidates = nonce_reuse_poly(&messages[0], &messages[1]).roots();
+
+    for other in messages[2..].iter() {
+        let p = nonce_reuse_poly(&messages[0], other);
+        candidates.retain(|&h| p.eval(h) == gf128::ZERO);
+    }
+    Ok(candidates)
+}
+
+/// Tag for (aad, cipher_text) under the nonce of known, given the hash key h
+pub fn forge_tag(h: Element, known: &GcmMessage, aad: &[u8], cipher_text: &[u8]) -> [u8; 16] {
+    let mask = gf128::from_block(&known.tag) ^ ghash(h, &known.aad, &known.cipher_text);
+    gf128::to_block(ghash(h, aad, cipher_text) ^ mask)
+}
+
+/// Local stand-in for a service that checks GCM messages with tags cut down to tag_len
+/// bytes, counting every query made against it. Built on `Gcm::with_short_tag`, so it takes
+/// tags of exactly that length and nothing else.
+pub struct TruncatedTagOracle<C: BlockCipher> {
+    gcm: Gcm<C>,
+    nonce: Vec<u8>,
+    queries: Cell<usize>,
+}
+
+impl<C: BlockCipher> TruncatedTagOracle<C> {
+    pub fn new(cipher: C, tag_len: usize) -> Result<TruncatedTagOracle<C>> {
+        let gcm = Gcm::with_short_tag(cipher, tag_len)?;
+        Ok(TruncatedTagOracle { gcm, nonce: random_bytes(12, 12), queries: Cell::new(0) })
+    }
+
+    /// cipher text and truncated tag for a message the attacker gets to observe
+    pub fn encrypt(&self, plain_text: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        self.gcm.encrypt(&self.nonce, &[], plain_text)
+    }
+
+    pub fn accepts(&self, cipher_text: &[u8], tag: &[u8]) -> bool {
+        self.queries.set(self.queries.get() + 1);
+        self.gcm.decrypt(&self.nonce, &[], cipher_text, tag).is_some()
+    }
+
+    pub fn queries(&self) -> usize {
+        self.queries.get()
+    }
+
+    /// the answer, for checking the attack
+    pub fn hash_key(&self) -> Element {
+        self.gcm.hash_key()
+    }
+}
+
+const LEFT_TO_CHANCE: usize = 4;
+
+// bit b of the tag is the coefficient of x^b, the most significant bit comes first
+fn tag_bit(e: Element, b: usize) -> bool {
+    (e >> (127 - b)) & 1 == 1
+}
+
+// repeated squaring, x^(2^i)
+fn frobenius(x: Element, i: usize) -> Element {
+    (0..i).fold(x, |acc, _| gf128::square(acc))
+}
+
+// index of the cipher text block (0 based) that gets multiplied by H^(2^i)
+fn block_for_power(blocks: usize, i: usize) -> usize {
+    blocks + 1 - (1 << i)
+}
+
+/// Recovers H given a cipher text of whole blocks and an oracle that reports whether a
+/// modified cipher text still matches its original tag_bits long tag. The cipher text length
+/// never changes, so the length block drops out of every difference. Gives up once
+/// max_queries forgeries have been tried, an oracle checking full tags never accepts one.
+pub fn recover_hash_key_truncated<F>(cipher_text: &[u8], tag_bits: usize, max_queries: usize, mut oracle: F) -> Result<Element>
+        where F: FnMut(&[u8]) -> bool {
+    if !cipher_text.len().is_multiple_of(16) {
+        return Err(Error::NotBlockAligned(cipher_text.len()));
+    }
+    if !(2..=128).contains(&tag_bits) {
+        return Err(Error::InvalidTagBits(tag_bits));
+    }
+    let blocks = cipher_text.len() / 16;
+
+    // squarings that land on a cipher text block, H^2 up to H^(2^n), at least three blocks
+    let n = (0..).take_while(|&i| (1usize << (i + 1)) <= blocks + 1).count();
+    if n < 2 {
+        return Err(Error::TooShort(cipher_text.len()));
+    }
+    let vars = n * 128;
+
+    let mut rng = thread_rng();
+    let mut equations: Vec<BitVec> = vec!();
+    let mut queries = 0;
+    loop {
+        // h lies in the span of basis, r unknowns are left
+        let basis: Vec<Element> = gf2::nullspace(&equations, 128).iter().map(|v| gf2::to_u128(v)).collect();
+        let r = basis.len();
+        if r == 0 {
+            return Err(Error::OracleInconsistent);
+        }
+        if r == 1 {
+            return Ok(basis[0]);
+        }
+
+        // zero out as many tag rows as the free bits allow but leave a few to chance, each
+        // accepted forgery then teaches that many equations for a handful of queries
+        let k = tag_bits.saturating_sub(LEFT_TO_CHANCE).max(1).min((vars - 1) / r);
+
+        // the error is sum_i d_i * X_l^(2^i), multiplying by the unit d = x^j is j shifts
+        let mut rows = vec![gf2::zeros(vars); k * r];
+        for (l, &x_l) in basis.iter().enumerate() {
+            for i in 1..=n {
+                let mut term = frobenius(x_l, i);
+                for j in 0..128 {
+                    for (b, row) in rows[l * k..(l + 1) * k].iter_mut().enumerate() {
+                        if tag_bit(term, b) {
+                            gf2::set(row, (i - 1) * 128 + j);
+                        }
+                    }
+                    term = gf128::mul_x(term);
+                }
+            }
+        }
+        let choices = gf2::nullspace(&rows, vars);
+
+        loop {
+            let mut d = gf2::zeros(vars);
+            for choice in choices.iter() {
+                if rng.gen::<bool>() {
+                    gf2::xor_into(&mut d, choice);
+                }
+            }
+            if d.iter().all(|&w| w == 0) {
+                continue;
+            }
+
+            let diffs: Vec<Element> = (0..n).map(|i| {
+                (0..128).filter(|&j| gf2::get(&d, i * 128 + j)).fold(0, |acc, j| acc | gf128::ONE >> j)
+            }).collect();
+
+            let mut forged = cipher_text.to_vec();
+            for (i, diff) in diffs.iter().enumerate() {
+                let idx = block_for_power(blocks, i + 1) * 16;
+                for (c, x) in forged[idx..idx + 16].iter_mut().zip(gf128::to_block(*diff).iter()) {
+                    *c ^= x;
+                }
+            }
+
+            if queries == max_queries {
+                return Err(Error::QueryBudgetExhausted(queries));
+            }
+            queries += 1;
+            if oracle(&forged) {
+                // every tag row of A_d is zero at h, the rows come from A_d applied to the units
+                let columns: Vec<Element> = (0..128).map(|c| {
+                    diffs.iter().enumerate().fold(gf128::ZERO, |acc, (i, &diff)| {
+                        acc ^ gf128::mul(diff, frobenius(1 << c, i + 1))
+                    })
+                }).collect();
+                for b in 0..tag_bits {
+                    let mut equation = gf2::zeros(128);
+                    for (c, &column) in columns.iter().enumerate() {
+                        if tag_bit(column, b) {
+                            gf2::set(&mut equation, c);
+                        }
+                    }
+                    equations.push(equation);
+                }
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use crypter::{random_aes_key, random_bytes};
+    use combine::xor_each;
+    use gcm::Gcm;
+
+    #[test]
+    fn ghash_poly_matches_ghash() {
+        let h = gf128::from_block(b"YELLOW SUBMARINE");
+        let (aad, cipher_text) = (b"some header", b"and a cipher text over a block");
+        assert_eq!(ghash_poly(aad, cipher_text).eval(h), ghash(h, aad, cipher_text));
+    }
+
+    #[test]
+    fn forbidden_attack() {
+        let gcm = Gcm::new(Aes::new(&random_aes_key()).unwrap()).unwrap();
+        let nonce = random_bytes(12, 12);
+
+        let messages: Vec<GcmMessage> = (0..3).map(|i| {
+            let aad = format!("message {}", i).into_bytes();
+            let (cipher_text, full) = gcm.encrypt(&nonce, &aad, &random_bytes(20, 48));
+            let mut tag = [0u8; 16];
+            tag.copy_from_slice(&full);
+            GcmMessage { aad, cipher_text, tag }
+        }).collect();
+
+        let candidates = recover_hash_keys(&messages).unwrap();
+        assert!(candidates.contains(&gcm.hash_key()));
+        match recover_hash_keys(&messages[..1]) {
+            Err(Error::TooFewMessages(1)) => (),
+            other => panic!("expected TooFewMessages, got {:?}", other),
+        }
+
+        // CTR is malleable, with H the bit flipped cipher text gets a valid tag as well
+        let h = candidates[0];
+        let forged = xor_each(&messages[0].cipher_text, b"\x01");
+        let tag = forge_tag(h, &messages[1], b"admin", &forged);
+        assert!(gcm.decrypt(&nonce, b"admin", &forged, &tag).is_some());
+    }
+
+    fn truncated_tag_attack(tag_len: usize, blocks: usize, max_queries: usize) {
+        let oracle = TruncatedTagOracle::new(Aes::new(&random_aes_key()).unwrap(), tag_len).unwrap();
+        let (cipher_text, tag) = oracle.encrypt(&random_bytes(blocks * 16, blocks * 16));
+        assert_eq!(tag.len(), tag_len);
+
+        let h = recover_hash_key_truncated(&cipher_text, tag_len * 8, max_queries, |forged| oracle.accepts(forged, &tag));
+        assert_eq!(h.unwrap(), oracle.hash_key());
+        assert!(oracle.queries() <= max_queries, "took {} queries", oracle.queries());
+    }
+
+    #[test]
+    fn truncated_tag_bad_input() {
+        let never = |_: &[u8]| false;
+        match recover_hash_key_truncated(&[0; 20], 16, 100, never) {
+            Err(Error::NotBlockAligned(20)) => (),
+            other => panic!("expected NotBlockAligned, got {:?}", other),
+        }
+        match recover_hash_key_truncated(&[0; 64], 1, 100, never) {
+            Err(Error::InvalidTagBits(1)) => (),
+            other => panic!("expected InvalidTagBits, got {:?}", other),
+        }
+        match recover_hash_key_truncated(&[0; 32], 16, 100, never) {
+            Err(Error::TooShort(32)) => (),
+            other => panic!("expected TooShort, got {:?}", other),
+        }
+
+        // a service that checks full tags takes none of the forgeries
+        match recover_hash_key_truncated(&[0; 64], 16, 100, never) {
+            Err(Error::QueryBudgetExhausted(100)) => (),
+            other => panic!("expected QueryBudgetExhausted, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_16_bit_tags() {
+        // the first forgery is a 2^-9 guess and the later ones get easier, about 1500
+        // queries all told
+        truncated_tag_attack(2, 256, 10_000);
+    }
+
+    #[test]
+    #[ignore]
+    fn truncated_32_bit_tags() {
+        // the first forgery is a 2^-16 guess over 2^16 blocks and every query hashes a
+        // megabyte, around half an hour even with --release
+        truncated_tag_attack(4, (1 << 16) - 1, 1 << 20);
+    }
+}
@@ -0,0 +1,76 @@
+// Arithmetic in GF(2^128) as used by GCM (NIST SP 800-38D).
+// Elements are u128s read big endian from a block, so the most significant bit is the
+// coefficient of x^0 and the field is reduced by x^128 + x^7 + x^2 + x + 1.
+
+pub type Element = u128;
+
+pub const ZERO: Element = 0;
+pub const ONE: Element = 1 << 127;
+
+// x^128 = x^7 + x^2 + x + 1, in the reflected bit order
+const R: Element = 0xe1 << 120;
+
+pub fn from_block(block: &[u8]) -> Element {
+    let mut bytes = [0u8; 16];
+    bytes[..block.len()].copy_from_slice(block);
+    u128::from_be_bytes(bytes)
+}
+
+pub fn to_block(x: Element) -> [u8; 16] {
+    x.to_be_bytes()
+}
+
+// multiplication by x, a right shift in this bit order
+pub fn mul_x(v: Element) -> Element {
+    (v >> 1) ^ (R & 0u128.wrapping_sub(v & 1))
+}
+
+// algorithm 1 of SP 800-38D, masks instead of branches so timing doesn't depend on the operands
+pub fn mul(x: Element, y: Element) -> Element {
+    let mut z = ZERO;
+    let mut v = y;
+    for i in 0..128 {
+        let bit = (x >> (127 - i)) & 1;
+        z ^= v & 0u128.wrapping_sub(bit);
+        v = mul_x(v);
+    }
+    z
+}
+
+pub fn square(x: Element) -> Element {
+    mul(x, x)
+}
+
+pub fn pow(x: Element, mut e: u128) -> Element {
+    let (mut result, mut base) = (ONE, x);
+    while e > 0 {
+        if e & 1 == 1 {
+            result = mul(result, base);
+        }
+        base = square(base);
+        e >>= 1;
+    }
+    result
+}
+
+// x^(2^128 - 2) is the inverse of any non-zero x
+pub fn inv(x: Element) -> Element {
+    assert!(x != ZERO, "zero has no inverse");
+    pow(x, u128::MAX - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identities() {
+        let a = from_block(b"YELLOW SUBMARINE");
+        let b = from_block(b"ICE ICE BABY\x04\x04\x04\x04");
+        assert_eq!(mul(a, ONE), a);
+        assert_eq!(mul(a, b), mul(b, a));
+        assert_eq!(mul(a, inv(a)), ONE);
+        assert_eq!(pow(a, 3), mul(a, square(a)));
+        assert_eq!(to_block(a), *b"YELLOW SUBMARINE");
+    }
+}
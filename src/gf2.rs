@@ -0,0 +1,112 @@
+// Linear algebra over GF(2), vectors are packed into u64 words with bit i of the vector at
+// bit i % 64 of word i / 64.
+
+pub type BitVec = Vec<u64>;
+
+pub fn zeros(len: usize) -> BitVec {
+    vec![0; len.div_ceil(64)]
+}
+
+pub fn get(v: &[u64], i: usize) -> bool {
+    (v[i / 64] >> (i % 64)) & 1 == 1
+}
+
+pub fn set(v: &mut [u64], i: usize) {
+    v[i / 64] |= 1 << (i % 64);
+}
+
+pub fn flip(v: &mut [u64], i: usize) {
+    v[i / 64] ^= 1 << (i % 64);
+}
+
+pub fn xor_into(v: &mut [u64], other: &[u64]) {
+    for (a, b) in v.iter_mut().zip(other.iter()) {
+        *a ^= b;
+    }
+}
+
+pub fn from_u128(x: u128) -> BitVec {
+    vec![x as u64, (x >> 64) as u64]
+}
+
+pub fn to_u128(v: &[u64]) -> u128 {
+    v[0] as u128 | (v[1] as u128) << 64
+}
+
+/// Basis of { x : M x = 0 } for a matrix given by its rows, each `cols` bits wide
+pub fn nullspace(rows: &[BitVec], cols: usize) -> Vec<BitVec> {
+    let mut rows = rows.to_vec();
+    let mut pivots: Vec<usize> = vec!();
+
+    // reduced row echelon form
+    let mut rank = 0;
+    for col in 0..cols {
+        let found = (rank..rows.len()).find(|&r| get(&rows[r], col));
+        if let Some(r) = found {
+            rows.swap(rank, r);
+            let pivot_row = rows[rank].clone();
+            for (idx, row) in rows.iter_mut().enumerate() {
+                if idx != rank && get(row, col) {
+                    xor_into(row, &pivot_row);
+                }
+            }
+            pivots.push(col);
+            rank += 1;
+        }
+    }
+
+    // every free column gives one basis vector
+    let mut is_pivot = vec![false; cols];
+    for &p in pivots.iter() {
+        is_pivot[p] = true;
+    }
+    (0..cols).filter(|&c| !is_pivot[c]).map(|free| {
+        let mut v = zeros(cols);
+        set(&mut v, free);
+        for (row, &p) in rows.iter().zip(pivots.iter()) {
+            if get(row, free) {
+                set(&mut v, p);
+            }
+        }
+        v
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(a: &[u64], b: &[u64]) -> bool {
+        a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc ^ (x & y).count_ones()) % 2 == 1
+    }
+
+    #[test]
+    fn nullspace_is_annihilated() {
+        // x0 + x1 = 0, x1 + x2 + x3 = 0 over 70 columns
+        let mut r1 = zeros(70);
+        set(&mut r1, 0);
+        set(&mut r1, 1);
+        let mut r2 = zeros(70);
+        set(&mut r2, 1);
+        set(&mut r2, 2);
+        set(&mut r2, 3);
+        let rows = vec![r1, r2];
+
+        let basis = nullspace(&rows, 70);
+        assert_eq!(basis.len(), 68);
+        for v in basis.iter() {
+            assert!(rows.iter().all(|r| !dot(r, v)));
+        }
+        assert_eq!(to_u128(&from_u128(0xdead_beef << 70)), 0xdead_beef << 70);
+    }
+
+    #[test]
+    fn flip_toggles() {
+        let mut v = zeros(70);
+        flip(&mut v, 69);
+        assert!(get(&v, 69));
+        assert!((0..69).all(|i| !get(&v, i)));
+        flip(&mut v, 69);
+        assert_eq!(v, zeros(70));
+    }
+}
@@ -0,0 +1,272 @@
+// CBC with the key reused as the IV (challenge 27). A receiver that complains about
+// non-ASCII plain text and quotes it back gives the key away in a single message.
+
+use std::result;
+use combine::xor_each_no_wrap;
+use error::{Error, Result};
+
+
+/// Recovers the key from an encryption oracle and a receiver that decrypts, checks for
+/// non-ASCII bytes and hands back the plain text in its error.
+///
+/// The cipher text C1, C2, C3 goes back as C1, 0, C1. The first block decrypts to D(C1) ^ key
+/// and the third to D(C1) ^ 0, xoring the two leaves the key. The rest of the message stays
+/// as it was so its padding still checks out.
+pub fn recover_key<E, D>(block_size: usize, encrypt: &E, decrypt: &D) -> Result<Vec<u8>>
+        where E: Fn(&[u8]) -> Vec<u8> + ?Sized, D: Fn(&[u8]) -> result::Result<(), Vec<u8>> + ?Sized {
+    // five blocks, the last two end up untouched whatever gets wrapped around the input
+    let cipher_text = encrypt(&vec![b'A'; 5 * block_size]);
+    if cipher_text.len() < 5 * block_size {
+        return Err(Error::NotBlockAligned(cipher_text.len()));
+    }
+
+    let first = &cipher_text[..block_size];
+    let tampered = [first, &vec![0; block_size], first, &cipher_text[3 * block_size..]].concat();
+
+    // the zero block decrypts to noise, something in there is bound to be above 0x7f
+    match decrypt(&tampered) {
+        Err(plain_text) if plain_text.len() >= 3 * block_size => {
+            Ok(xor_each_no_wrap(&plain_text[..block_size], &plain_text[2 * block_size..3 * block_size]))
+        }
+        _ => Err(Error::OracleSilent),
+    }
+}
+
+/// A call passing the key as the IV
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IvKeyReuse {
+    /// 1 based
+    pub line: usize,
+    pub key: String,
+}
+
+// the source with comments blanked out, string and char literals kept so offsets and lines
+// still match. Knows nested block comments, raw strings, char literals and lifetimes, but not
+// what a macro does with its input.
+fn strip_comments(source: &str) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let mut stripped = String::with_capacity(source.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        if let Some(end) = comment_end(&chars, i) {
+            stripped.extend(chars[i..end].iter().map(|&c| if c == '\n' { '\n' } else { ' ' }));
+            i = end;
+        } else {
+            let end = literal_end(&chars, i).unwrap_or(i + 1);
+            stripped.extend(&chars[i..end]);
+            i = end;
+        }
+    }
+    stripped
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the end of the comment starting at i, line comments stop short of the line break
+fn comment_end(chars: &[char], i: usize) -> Option<usize> {
+    match (chars[i], chars.get(i + 1).copied()) {
+        ('/', Some('/')) => Some(chars[i..].iter().position(|&c| c == '\n').map_or(chars.len(), |n| i + n)),
+        ('/', Some('*')) => {
+            // block comments nest
+            let (mut depth, mut j) = (0, i);
+            while j < chars.len() {
+                match (chars[j], chars.get(j + 1).copied()) {
+                    ('/', Some('*')) => {
+                        depth += 1;
+                        j += 2;
+                    }
+                    ('*', Some('/')) => {
+                        depth -= 1;
+                        j += 2;
+                        if depth == 0 {
+                            return Some(j);
+                        }
+                    }
+                    _ => j += 1,
+                }
+            }
+            Some(chars.len())
+        }
+        _ => None,
+    }
+}
+
+// the end of the string, raw string or char literal starting at i
+fn literal_end(chars: &[char], i: usize) -> Option<usize> {
+    let starts_token = |at: usize| at == 0 || !is_ident(chars[at - 1]);
+    match chars[i] {
+        '"' => {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '"' {
+                j += if chars[j] == '\\' { 2 } else { 1 };
+            }
+            Some((j + 1).min(chars.len()))
+        }
+        // r"..", r#".."# and the byte string versions, no escapes inside
+        'r' if starts_token(i) || (chars[i - 1] == 'b' && starts_token(i - 1)) => {
+            let hashes = chars[i + 1..].iter().take_while(|&&c| c == '#').count();
+            if chars.get(i + 1 + hashes) != Some(&'"') {
+                return None;
+            }
+            let body = i + hashes + 2;
+            let closing: Vec<char> = Some('"').into_iter().chain(vec!['#'; hashes]).collect();
+            Some(chars[body..].windows(closing.len()).position(|w| w == &closing[..])
+                .map_or(chars.len(), |n| body + n + closing.len()))
+        }
+        // '"' and '\'' are chars, 'a on its own is a lifetime
+        '\'' => match (chars.get(i + 1).copied(), chars.get(i + 2).copied()) {
+            (Some('\\'), _) => chars.get(i + 3..)?.iter().position(|&c| c == '\'').map(|n| i + n + 4),
+            (Some(_), Some('\'')) => Some(i + 3),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// the arguments of a call whose opening parenthesis is right before args, split on top
+// level commas
+fn call_arguments(args: &str) -> Vec<String> {
+    let mut split = vec!(String::new());
+    let mut depth = 0;
+    for c in args.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth == 0 => break,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(String::new());
+                continue;
+            }
+            _ => (),
+        }
+        split.last_mut().unwrap().push(c);
+    }
+    split
+}
+
+// what an argument refers to, without the borrows, Some and reslicing around it
+fn normalize(arg: &str) -> String {
+    let mut arg: String = arg.chars().filter(|c| !c.is_whitespace()).collect();
+    loop {
+        let before = arg.clone();
+        if arg.starts_with("Some(") && arg.ends_with(')') {
+            arg = arg[5..arg.len() - 1].to_string();
+        }
+        arg = arg.trim_start_matches('&').to_string();
+        for suffix in &["[..]", ".as_slice()", ".as_ref()", ".as_bytes()"] {
+            if arg.ends_with(suffix) {
+                arg.truncate(arg.len() - suffix.len());
+            }
+        }
+        if arg == before {
+            return arg;
+        }
+    }
+}
+
+/// Flags `aes_cbc` calls whose IV argument is the key argument. Goes by the text of the
+/// source, so a copy of the key under another name slips through.
+pub fn lint_iv_equals_key(source: &str) -> Vec<IvKeyReuse> {
+    let source = strip_comments(source);
+    let mut found = vec!();
+
+    for (at, _) in source.match_indices("aes_cbc(") {
+        // a call, not a definition or a longer name ending the same way
+        let before = source[..at].trim_end();
+        let ident_before = source[..at].chars().last().is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if ident_before || before.ends_with("fn") {
+            continue;
+        }
+
+        let args = call_arguments(&source[at + "aes_cbc(".len()..]);
+        if args.len() < 3 {
+            continue;
+        }
+        let (key, iv) = (normalize(&args[0]), normalize(&args[2]));
+        if !key.is_empty() && key == iv {
+            found.push(IvKeyReuse { line: source[..at].matches('\n').count() + 1, key });
+        }
+    }
+    found
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use conversions::{pad_pkcs7, Padding, Pkcs7};
+    use crypter::{cbc, random_aes_key};
+    use openssl::symm::Mode;
+
+    #[test]
+    fn key_from_complaint() {
+        let key = random_aes_key();
+        let cipher = Aes::new(&key).unwrap();
+        let encrypt = |input: &[u8]| {
+            let mut plain_text = [&b"comment1=cooking%20MCs;userdata="[..], input].concat();
+            pad_pkcs7(&mut plain_text, 16).unwrap();
+            cbc(&cipher, &key, &plain_text, Mode::Encrypt).unwrap()
+        };
+        let decrypt = |cipher_text: &[u8]| {
+            let mut plain_text = cbc(&cipher, &key, cipher_text, Mode::Decrypt).unwrap();
+            Pkcs7.unpad(&mut plain_text, 16).map_err(|_| vec!())?;
+            if plain_text.iter().any(|&b| b > 0x7f) { Err(plain_text) } else { Ok(()) }
+        };
+        assert_eq!(recover_key(16, &encrypt, &decrypt).unwrap(), key);
+
+        // a receiver that keeps quiet
+        match recover_key(16, &encrypt, &|_: &[u8]| Err(vec!())) {
+            Err(Error::OracleSilent) => (),
+            other => panic!("expected OracleSilent, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lint() {
+        let source = r#"
+            fn aes_cbc(key: &[u8], input: &[u8], iv: Option<&[u8]>) {}
+            aes_cbc(&key, &input, Some(&key), &mut out, Mode::Encrypt).unwrap();
+            aes_cbc(key, &input, Some(&iv), &mut out, Mode::Encrypt);
+            // aes_cbc(key, &input, Some(key), &mut out, Mode::Encrypt);
+            let s = "aes_cbc(// not a comment";
+            my_aes_cbc(k, &input, Some(k));
+            aes_cbc(
+                self.key.as_slice(), &[f(a, b), 1],
+                Some(&self.key[..]), &mut out, Mode::Decrypt);
+        "#;
+        let found = lint_iv_equals_key(source);
+        assert_eq!(found, vec![
+            IvKeyReuse { line: 3, key: "key".to_string() },
+            IvKeyReuse { line: 8, key: "self.key".to_string() },
+        ]);
+
+        // quotes in char literals and raw strings don't open a string, comments of either
+        // kind hide calls
+        let source = r##"
+            let quote = '"'; let escaped = '\''; fn f<'a>(x: &'a str) {}
+            aes_cbc(&k, &input, Some(&k), &mut out, Mode::Encrypt);
+            let raw = r#"a " // b"#; aes_cbc(k, &input, Some(k));
+            /* aes_cbc(k, &input, Some(k));
+               /* nested */ aes_cbc(k, &input, Some(k)); */
+            aes_cbc(&key, &input, Some(&iv), &mut out, Mode::Encrypt);
+            aes_cbc(&key, b"\"", Some(&key), &mut out, Mode::Encrypt);
+        "##;
+        let found = lint_iv_equals_key(source);
+        assert_eq!(found, vec![
+            IvKeyReuse { line: 3, key: "k".to_string() },
+            IvKeyReuse { line: 4, key: "k".to_string() },
+            IvKeyReuse { line: 8, key: "key".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn comments_blanked() {
+        let source = "let a = '\"'; // x\nlet b = r##\"/* \"# */\"##; /* y\n/* z */ */ let c = 'a';";
+        assert_eq!(strip_comments(source),
+            "let a = '\"';     \nlet b = r##\"/* \"# */\"##;     \n           let c = 'a';");
+    }
+}
@@ -3,12 +3,26 @@ extern crate openssl;
 extern crate rand;
 extern crate num;
 
+pub mod error;
 pub mod conversions;
 pub mod combine;
 pub mod crack;
+pub mod crib;
 pub mod text;
 pub mod measure;
 pub mod crypter;
+pub mod aes;
+pub mod stream;
+pub mod parallel;
+pub mod padding_oracle;
+pub mod ecb_attack;
+pub mod bitflip;
+pub mod iv_key;
+pub mod gf128;
+pub mod gcm;
+pub mod poly;
+pub mod gf2;
+pub mod gcm_attack;
 pub mod mersenne;
 pub mod sha1;
 pub mod md4;
@@ -18,3 +32,5 @@ pub mod dh;
 pub mod rsa;
 
 pub mod challenges;
+
+pub use error::{Error, PaddingError, Result};
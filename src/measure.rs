@@ -9,3 +9,19 @@ fn test_hamming() {
 
     assert_eq!(hamming(&str1, &str2), 37);
 }
+
+// compares every byte regardless of where the first difference is, so the time taken
+// doesn't leak how much of a secret value was guessed correctly
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter().zip(b.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[test]
+fn test_constant_time_eq() {
+    assert!(constant_time_eq(b"YELLOW SUBMARINE", b"YELLOW SUBMARINE"));
+    assert!(!constant_time_eq(b"YELLOW SUBMARINE", b"YELLOW SUBMARINF"));
+    assert!(!constant_time_eq(b"YELLOW", b"YELLOW SUBMARINE"));
+}
@@ -0,0 +1,320 @@
+// The CBC padding oracle attack (Vaudenay). CBC decryption xors the block cipher output for
+// a block, its intermediate state, with the previous cipher text block. Whoever controls that
+// previous block and learns whether the padding came out valid can recover the intermediate
+// state a byte at a time, from the last byte forward: a byte is found once the guess for it
+// makes the tail of the block a valid padding of the matching length.
+//
+// With intermediate states on demand we can both decrypt and, CBC-R, encrypt: starting from
+// an arbitrary last block, every earlier block is picked so the one after it decrypts to the
+// chosen plain text.
+//
+// The oracle has to check every padding byte. ISO 10126 fills the padding with random bytes
+// and only the length byte can be checked, so every guess that puts the last byte in range
+// looks like a hit. Attacks under such a scheme fail with UnsupportedPadding rather than
+// returning the wrong plain text.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use conversions::Padding;
+use crypter::{crypt, crypt_padded, random_bytes, BlockCipher, CipherMode};
+use error::{Error, Result};
+use openssl::symm::Mode;
+use parallel::Parallel;
+
+
+/// Anything that tells whether a CBC cipher text decrypts to valid padding under an IV, a
+/// closure holding the key, a remote endpoint behind a socket...
+pub trait PaddingOracle {
+    fn is_valid(&self, iv: &[u8], cipher_text: &[u8]) -> bool;
+}
+
+impl<F: Fn(&[u8], &[u8]) -> bool> PaddingOracle for F {
+    fn is_valid(&self, iv: &[u8], cipher_text: &[u8]) -> bool {
+        self(iv, cipher_text)
+    }
+}
+
+/// Local stand-in for a server that decrypts CBC and reports bad padding, counts its queries
+pub struct CbcPaddingOracle<C: BlockCipher, P: Padding> {
+    cipher: C,
+    padding: P,
+    queries: AtomicUsize,
+}
+
+impl<C: BlockCipher, P: Padding> CbcPaddingOracle<C, P> {
+    pub fn new(cipher: C, padding: P) -> CbcPaddingOracle<C, P> {
+        CbcPaddingOracle { cipher, padding, queries: AtomicUsize::new(0) }
+    }
+
+    pub fn encrypt(&self, iv: &[u8], plain_text: &[u8]) -> Result<Vec<u8>> {
+        crypt_padded(&self.cipher, CipherMode::Cbc, &self.padding, Some(iv), plain_text, Mode::Encrypt)
+    }
+
+    pub fn decrypt(&self, iv: &[u8], cipher_text: &[u8]) -> Result<Vec<u8>> {
+        crypt_padded(&self.cipher, CipherMode::Cbc, &self.padding, Some(iv), cipher_text, Mode::Decrypt)
+    }
+
+    pub fn queries(&self) -> usize {
+        self.queries.load(Ordering::Relaxed)
+    }
+}
+
+impl<C: BlockCipher, P: Padding> PaddingOracle for CbcPaddingOracle<C, P> {
+    fn is_valid(&self, iv: &[u8], cipher_text: &[u8]) -> bool {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        match crypt(&self.cipher, CipherMode::Cbc, Some(iv), cipher_text, Mode::Decrypt) {
+            Ok(plain_text) => self.padding.validate(&plain_text, self.cipher.block_size()),
+            Err(_) => false,
+        }
+    }
+}
+
+// the last n bytes of a block padded by the scheme, whatever the data in front of them
+fn pad_pattern<P: Padding + ?Sized>(padding: &P, block_size: usize, n: usize) -> Result<Vec<u8>> {
+    let mut block = vec![0; block_size - n];
+    padding.pad(&mut block, block_size)?;
+    Ok(block.split_off(block_size - n))
+}
+
+// Tests one guess for probe[idx]. A hit can be a false positive when the bytes in front
+// happen to extend the padding, the classic case being a last byte that comes out as \x02
+// right behind a byte that already is \x02. Those go away when the byte in front is
+// changed, a real hit doesn't care.
+fn try_guess<O: PaddingOracle + ?Sized>(oracle: &O, probe: &[u8], block: &[u8], idx: usize, guess: u8) -> bool {
+    let mut probe = probe.to_vec();
+    probe[idx] = guess;
+    if !oracle.is_valid(&probe, block) {
+        return false;
+    }
+    if idx == 0 {
+        return true;
+    }
+
+    probe[idx - 1] ^= 0xff;
+    oracle.is_valid(&probe, block)
+}
+
+// recovers the intermediate state of block, find searches for the byte at idx of the probe
+fn intermediate_by<P, F>(padding: &P, block: &[u8], find: F) -> Result<Vec<u8>>
+        where P: Padding + ?Sized, F: Fn(&[u8], usize) -> Option<u8> {
+    if !padding.checks_filler() {
+        return Err(Error::UnsupportedPadding);
+    }
+    let block_size = block.len();
+    let mut state = vec![0; block_size];
+    let mut probe = vec![0; block_size];
+
+    for idx in (0..block_size).rev() {
+        let pattern = pad_pattern(padding, block_size, block_size - idx)?;
+        // everything after idx already decrypts to the rest of the pattern
+        for (j, &p) in pattern.iter().enumerate().skip(1) {
+            probe[idx + j] = state[idx + j] ^ p;
+        }
+
+        let guess = find(&probe, idx).ok_or(Error::OracleExhausted)?;
+        probe[idx] = guess;
+        state[idx] = guess ^ pattern[0];
+    }
+    Ok(state)
+}
+
+/// The block cipher decryption of a single block, before the CBC xor
+pub fn intermediate<O, P>(oracle: &O, padding: &P, block: &[u8]) -> Result<Vec<u8>>
+        where O: PaddingOracle + ?Sized, P: Padding + ?Sized {
+    intermediate_by(padding, block, |probe, idx| {
+        (0..=255).find(|&guess| try_guess(oracle, probe, block, idx, guess))
+    })
+}
+
+/// `intermediate` with the 256 guesses for each byte spread over the driver's threads
+pub fn intermediate_parallel<O, P>(parallel: &Parallel, oracle: &O, padding: &P, block: &[u8]) -> Result<Vec<u8>>
+        where O: PaddingOracle + Sync + ?Sized, P: Padding + ?Sized {
+    let counted = Counted { oracle, parallel };
+    intermediate_by(padding, block, |probe, idx| {
+        parallel.find_byte(|guess| try_guess(&counted, probe, block, idx, guess))
+    })
+}
+
+// passes queries through the driver so they are counted
+struct Counted<'a, O: ?Sized + 'a> {
+    oracle: &'a O,
+    parallel: &'a Parallel,
+}
+
+impl<'a, O: PaddingOracle + ?Sized> PaddingOracle for Counted<'a, O> {
+    fn is_valid(&self, iv: &[u8], cipher_text: &[u8]) -> bool {
+        self.parallel.query(|| self.oracle.is_valid(iv, cipher_text))
+    }
+}
+
+pub fn decrypt_block<O, P>(oracle: &O, padding: &P, previous: &[u8], block: &[u8]) -> Result<Vec<u8>>
+        where O: PaddingOracle + ?Sized, P: Padding + ?Sized {
+    let state = intermediate(oracle, padding, block)?;
+    Ok(state.iter().zip(previous.iter()).map(|(s, p)| s ^ p).collect())
+}
+
+fn check_message(block_size: usize, iv: &[u8], cipher_text: &[u8]) -> Result<()> {
+    if cipher_text.is_empty() || !cipher_text.len().is_multiple_of(block_size) {
+        return Err(Error::NotBlockAligned(cipher_text.len()));
+    }
+    if iv.len() != block_size {
+        return Err(Error::InvalidIvLength(iv.len()));
+    }
+    Ok(())
+}
+
+/// Decrypts a whole message and strips its padding
+pub fn decrypt<O, P>(oracle: &O, padding: &P, block_size: usize, iv: &[u8], cipher_text: &[u8]) -> Result<Vec<u8>>
+        where O: PaddingOracle + ?Sized, P: Padding + ?Sized {
+    check_message(block_size, iv, cipher_text)?;
+
+    let mut plain_text = vec!();
+    let mut previous = iv;
+    for block in cipher_text.chunks(block_size) {
+        plain_text.extend(decrypt_block(oracle, padding, previous, block)?);
+        previous = block;
+    }
+    padding.unpad(&mut plain_text, block_size)?;
+    Ok(plain_text)
+}
+
+/// `decrypt` with the blocks attacked concurrently, every block only needs the one in front
+pub fn decrypt_parallel<O, P>(parallel: &Parallel, oracle: &O, padding: &P, block_size: usize, iv: &[u8], cipher_text: &[u8]) -> Result<Vec<u8>>
+        where O: PaddingOracle + Sync + ?Sized, P: Padding + Sync + ?Sized {
+    check_message(block_size, iv, cipher_text)?;
+
+    let counted = Counted { oracle, parallel };
+    let chained = [iv, cipher_text].concat();
+    let pairs: Vec<&[u8]> = chained.windows(2 * block_size).step_by(block_size).collect();
+    let blocks = parallel.map(&pairs, |pair| {
+        let (previous, block) = pair.split_at(block_size);
+        decrypt_block(&counted, padding, previous, block)
+    });
+
+    let mut plain_text = blocks.into_iter().collect::<Result<Vec<_>>>()?.concat();
+    padding.unpad(&mut plain_text, block_size)?;
+    Ok(plain_text)
+}
+
+// CBC-R built back to front, the last block can be anything and every block before it is
+// the intermediate state of its successor xored with the plain text wanted there
+fn forge_by<P, F>(padding: &P, block_size: usize, plain_text: &[u8], intermediate: F) -> Result<(Vec<u8>, Vec<u8>)>
+        where P: Padding + ?Sized, F: Fn(&[u8]) -> Result<Vec<u8>> {
+    let mut padded = plain_text.to_vec();
+    padding.pad(&mut padded, block_size)?;
+
+    let mut blocks = vec![random_bytes(block_size, block_size)];
+    for target in padded.chunks(block_size).rev() {
+        let state = intermediate(&blocks[0])?;
+        blocks.insert(0, state.iter().zip(target.iter()).map(|(s, t)| s ^ t).collect());
+    }
+
+    let iv = blocks.remove(0);
+    Ok((iv, blocks.concat()))
+}
+
+/// CBC-R, encrypts plain_text without the key. Returns the IV and the cipher text, which
+/// the oracle's owner decrypts to the padded plain_text.
+pub fn forge<O, P>(oracle: &O, padding: &P, block_size: usize, plain_text: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>
+        where O: PaddingOracle + ?Sized, P: Padding + ?Sized {
+    forge_by(padding, block_size, plain_text, |block| intermediate(oracle, padding, block))
+}
+
+/// `forge` with the guesses spread over the driver's threads, the blocks themselves depend
+/// on each other
+pub fn forge_parallel<O, P>(parallel: &Parallel, oracle: &O, padding: &P, block_size: usize, plain_text: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>
+        where O: PaddingOracle + Sync + ?Sized, P: Padding + ?Sized {
+    forge_by(padding, block_size, plain_text, |block| intermediate_parallel(parallel, oracle, padding, block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use conversions::{Pkcs7, AnsiX923, Iso10126, Iso7816};
+    use crypter::{random_aes_key, OpensslCipher};
+    use openssl::symm::Cipher;
+
+    #[test]
+    fn decrypt_with_each_padding() {
+        let plain_text = b"Cooking MC's like a pound of bacon";
+        let iv = random_bytes(16, 16);
+        let schemes: [&dyn Padding; 3] = [&Pkcs7, &AnsiX923, &Iso7816];
+        for &padding in schemes.iter() {
+            let oracle = CbcPaddingOracle::new(Aes::new(&random_aes_key()).unwrap(), padding);
+            let cipher_text = oracle.encrypt(&iv, plain_text).unwrap();
+            assert_eq!(decrypt(&oracle, padding, 16, &iv, &cipher_text).unwrap(), &plain_text[..]);
+            assert!(oracle.queries() > 0);
+        }
+
+        let oracle = CbcPaddingOracle::new(Aes::new(&random_aes_key()).unwrap(), Iso10126);
+        let cipher_text = oracle.encrypt(&iv, plain_text).unwrap();
+        match decrypt(&oracle, &Iso10126, 16, &iv, &cipher_text) {
+            Err(Error::UnsupportedPadding) => (),
+            other => panic!("expected UnsupportedPadding, got {:?}", other),
+        }
+        assert!(forge(&oracle, &Iso10126, 16, plain_text).is_err());
+    }
+
+    #[test]
+    fn last_byte_false_positive() {
+        // an intermediate state ending in \x02\x03, against the zeroed probe the guess 1
+        // gives \x02\x02 and is tried before the real hit at 2
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = CbcPaddingOracle::new(&cipher, Pkcs7);
+        let mut state = random_bytes(16, 16);
+        state[14] = 2;
+        state[15] = 3;
+
+        let mut block = state.clone();
+        cipher.encrypt_block(&mut block);
+        assert_eq!(intermediate(&oracle, &Pkcs7, &block).unwrap(), state);
+    }
+
+    #[test]
+    fn eight_byte_blocks() {
+        let oracle = CbcPaddingOracle::new(OpensslCipher::new(Cipher::des_ede3(), &random_bytes(24, 24)).unwrap(), Pkcs7);
+        let iv = random_bytes(8, 8);
+        let cipher_text = oracle.encrypt(&iv, b"attack at dawn").unwrap();
+        assert_eq!(decrypt(&oracle, &Pkcs7, 8, &iv, &cipher_text).unwrap(), b"attack at dawn");
+    }
+
+    #[test]
+    fn forge_cbc_r() {
+        let cipher = Aes::new(&random_aes_key()).unwrap();
+        let oracle = |iv: &[u8], cipher_text: &[u8]| {
+            crypt_padded(&cipher, CipherMode::Cbc, &Pkcs7, Some(iv), cipher_text, Mode::Decrypt).is_ok()
+        };
+
+        let (iv, cipher_text) = forge(&oracle, &Pkcs7, 16, b"role=admin;uid=1;comment=forged").unwrap();
+        let decrypted = crypt_padded(&cipher, CipherMode::Cbc, &Pkcs7, Some(&iv), &cipher_text, Mode::Decrypt).unwrap();
+        assert_eq!(decrypted, b"role=admin;uid=1;comment=forged");
+    }
+
+    #[test]
+    fn parallel_attacks() {
+        let oracle = CbcPaddingOracle::new(Aes::new(&random_aes_key()).unwrap(), Pkcs7);
+        let iv = random_bytes(16, 16);
+        let plain_text = random_bytes(70, 70);
+        let cipher_text = oracle.encrypt(&iv, &plain_text).unwrap();
+
+        let parallel = Parallel::new(4);
+        assert_eq!(decrypt_parallel(&parallel, &oracle, &Pkcs7, 16, &iv, &cipher_text).unwrap(), plain_text);
+        assert_eq!(parallel.queries(), oracle.queries());
+
+        let block = &cipher_text[16..32];
+        let serial = intermediate(&oracle, &Pkcs7, block).unwrap();
+        assert_eq!(intermediate_parallel(&Parallel::new(8), &oracle, &Pkcs7, block).unwrap(), serial);
+
+        let (iv, forged) = forge_parallel(&parallel, &oracle, &Pkcs7, 16, b"admin=true").unwrap();
+        assert_eq!(oracle.decrypt(&iv, &forged).unwrap(), b"admin=true");
+    }
+
+    #[test]
+    fn dead_oracle() {
+        let oracle = |_: &[u8], _: &[u8]| false;
+        match decrypt(&oracle, &Pkcs7, 16, &[0; 16], &[0; 32]) {
+            Err(Error::OracleExhausted) => (),
+            other => panic!("expected OracleExhausted, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// Runs oracle attacks over several threads. Slow oracles (anything across a socket) spend
+// nearly all their time waiting, so the byte guesses or independent blocks of an attack can
+// be queried concurrently. The number of threads is capped so the target isn't flooded, and
+// the queries made through the driver are counted.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+
+pub struct Parallel {
+    limit: usize,
+    queries: AtomicUsize,
+}
+
+impl Parallel {
+    /// at most limit oracle queries in flight at once
+    pub fn new(limit: usize) -> Parallel {
+        assert!(limit > 0, "need at least one thread");
+        Parallel { limit, queries: AtomicUsize::new(0) }
+    }
+
+    /// one thread per core
+    pub fn per_core() -> Parallel {
+        Parallel::new(thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn queries(&self) -> usize {
+        self.queries.load(Ordering::Relaxed)
+    }
+
+    /// Makes one counted oracle query
+    pub fn query<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        f()
+    }
+
+    /// The smallest byte that passes test, same as a serial search from 0 would find. The
+    /// guesses are dealt out round robin, threads stop once a smaller guess has passed.
+    pub fn find_byte<F: Fn(u8) -> bool + Sync>(&self, test: F) -> Option<u8> {
+        let best = AtomicUsize::new(256);
+        let threads = self.limit.min(256);
+
+        thread::scope(|s| {
+            for first in 0..threads {
+                let (best, test) = (&best, &test);
+                s.spawn(move || {
+                    for guess in (first..256).step_by(threads) {
+                        if guess > best.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if test(guess as u8) {
+                            best.fetch_min(guess, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        match best.into_inner() {
+            256 => None,
+            guess => Some(guess as u8),
+        }
+    }
+
+    /// f over every item, at most limit at a time. The results keep the order of the items.
+    pub fn map<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(&self, items: &[T], f: F) -> Vec<R> {
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+
+        thread::scope(|s| {
+            for _ in 0..self.limit.min(items.len()) {
+                s.spawn(|| loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    if idx >= items.len() {
+                        break;
+                    }
+                    let result = f(&items[idx]);
+                    *results[idx].lock().unwrap() = Some(result);
+                });
+            }
+        });
+
+        results.into_iter().map(|r| r.into_inner().unwrap().expect("every item is mapped")).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn find_smallest_byte() {
+        for &limit in &[1, 3, 16, 300] {
+            let parallel = Parallel::new(limit);
+            assert_eq!(parallel.find_byte(|b| b % 37 == 36 || b == 200), Some(36));
+            assert_eq!(parallel.find_byte(|b| b == 255), Some(255));
+            assert_eq!(parallel.find_byte(|_| false), None);
+        }
+    }
+
+    #[test]
+    fn map_in_order_within_limit() {
+        let parallel = Parallel::new(3);
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let items: Vec<usize> = (0..20).collect();
+
+        let squares = parallel.map(&items, |&x| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(2));
+            running.fetch_sub(1, Ordering::SeqCst);
+            parallel.query(|| x * x)
+        });
+
+        assert_eq!(squares, items.iter().map(|x| x * x).collect::<Vec<_>>());
+        assert!(peak.into_inner() <= 3);
+        assert_eq!(parallel.queries(), 20);
+    }
+}
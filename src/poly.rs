@@ -0,0 +1,218 @@
+// Polynomials with coefficients in GF(2^128), enough to find the roots of a GHASH
+// difference polynomial: gcd with X^(2^128) - X keeps the distinct linear factors, then
+// Cantor-Zassenhaus equal degree factorization splits them apart.
+
+use gf128::{self, Element};
+use rand::{thread_rng, Rng};
+
+
+/// coeffs[i] is the coefficient of X^i, kept without trailing zeros
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poly {
+    coeffs: Vec<Element>,
+}
+
+impl Poly {
+    pub fn new(mut coeffs: Vec<Element>) -> Poly {
+        while coeffs.last() == Some(&gf128::ZERO) {
+            coeffs.pop();
+        }
+        Poly { coeffs }
+    }
+
+    pub fn zero() -> Poly {
+        Poly { coeffs: vec!() }
+    }
+
+    pub fn constant(c: Element) -> Poly {
+        Poly::new(vec![c])
+    }
+
+    // X
+    pub fn x() -> Poly {
+        Poly::new(vec![gf128::ZERO, gf128::ONE])
+    }
+
+    pub fn coeffs(&self) -> &[Element] {
+        &self.coeffs
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeffs.is_empty()
+    }
+
+    // the zero polynomial has no degree
+    pub fn degree(&self) -> Option<usize> {
+        self.coeffs.len().checked_sub(1)
+    }
+
+    fn leading(&self) -> Element {
+        *self.coeffs.last().expect("zero polynomial has no leading coefficient")
+    }
+
+    pub fn eval(&self, x: Element) -> Element {
+        self.coeffs.iter().rev().fold(gf128::ZERO, |acc, &c| gf128::mul(acc, x) ^ c)
+    }
+
+    // addition and subtraction are the same in characteristic two
+    pub fn add(&self, other: &Poly) -> Poly {
+        let len = self.coeffs.len().max(other.coeffs.len());
+        let at = |p: &Poly, i: usize| *p.coeffs.get(i).unwrap_or(&gf128::ZERO);
+        Poly::new((0..len).map(|i| at(self, i) ^ at(other, i)).collect())
+    }
+
+    pub fn mul(&self, other: &Poly) -> Poly {
+        if self.is_zero() || other.is_zero() {
+            return Poly::zero();
+        }
+        let mut coeffs = vec![gf128::ZERO; self.coeffs.len() + other.coeffs.len() - 1];
+        for (i, &a) in self.coeffs.iter().enumerate() {
+            for (j, &b) in other.coeffs.iter().enumerate() {
+                coeffs[i + j] ^= gf128::mul(a, b);
+            }
+        }
+        Poly::new(coeffs)
+    }
+
+    pub fn scale(&self, c: Element) -> Poly {
+        Poly::new(self.coeffs.iter().map(|&a| gf128::mul(a, c)).collect())
+    }
+
+    pub fn monic(&self) -> Poly {
+        if self.is_zero() {
+            return Poly::zero();
+        }
+        self.scale(gf128::inv(self.leading()))
+    }
+
+    /// quotient and remainder of long division
+    pub fn div_rem(&self, divisor: &Poly) -> (Poly, Poly) {
+        let divisor_degree = divisor.degree().expect("division by the zero polynomial");
+        let lead_inv = gf128::inv(divisor.leading());
+
+        let mut rem = self.coeffs.clone();
+        if rem.len() <= divisor_degree {
+            return (Poly::zero(), self.clone());
+        }
+        let mut quot = vec![gf128::ZERO; rem.len() - divisor_degree];
+        for i in (0..quot.len()).rev() {
+            let c = gf128::mul(rem[i + divisor_degree], lead_inv);
+            quot[i] = c;
+            for (j, &d) in divisor.coeffs.iter().enumerate() {
+                rem[i + j] ^= gf128::mul(c, d);
+            }
+        }
+        rem.truncate(divisor_degree);
+        (Poly::new(quot), Poly::new(rem))
+    }
+
+    pub fn rem(&self, modulus: &Poly) -> Poly {
+        self.div_rem(modulus).1
+    }
+
+    /// monic greatest common divisor
+    pub fn gcd(&self, other: &Poly) -> Poly {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while !b.is_zero() {
+            let r = a.rem(&b);
+            a = b;
+            b = r;
+        }
+        a.monic()
+    }
+
+    /// the product of the distinct linear factors, gcd(f, X^(2^128) - X)
+    pub fn linear_part(&self) -> Poly {
+        // X^(2^128) mod f, by squaring 128 times
+        let mut x_q = Poly::x().rem(self);
+        for _ in 0..128 {
+            x_q = x_q.mul(&x_q).rem(self);
+        }
+        self.gcd(&x_q.add(&Poly::x()))
+    }
+
+    /// Roots of the polynomial, each listed once
+    pub fn roots(&self) -> Vec<Element> {
+        if self.is_zero() {
+            return vec!();
+        }
+        let mut roots: Vec<Element> = equal_degree_linear(&self.monic().linear_part())
+            .iter().map(|factor| factor.coeffs[0]).collect();
+        roots.sort();
+        roots
+    }
+}
+
+// a + a^2 + a^4 + ... + a^(2^127) mod f, the absolute trace lands in GF(2) for roots of f so
+// gcd(f, trace) picks out the roots with trace zero, about half of them
+fn trace_mod(a: &Poly, f: &Poly) -> Poly {
+    let mut term = a.rem(f);
+    let mut acc = term.clone();
+    for _ in 1..128 {
+        term = term.mul(&term).rem(f);
+        acc = acc.add(&term);
+    }
+    acc
+}
+
+fn random_poly(degree: usize) -> Poly {
+    let mut rng = thread_rng();
+    Poly::new((0..degree).map(|_| ((rng.gen::<u64>() as u128) << 64) | rng.gen::<u64>() as u128).collect())
+}
+
+// Cantor-Zassenhaus for a monic product of distinct linear factors
+fn equal_degree_linear(f: &Poly) -> Vec<Poly> {
+    match f.degree() {
+        None | Some(0) => return vec!(),
+        Some(1) => return vec![f.clone()],
+        _ => (),
+    }
+
+    let degree = f.degree().unwrap();
+    loop {
+        let g = f.gcd(&trace_mod(&random_poly(degree), f));
+        let split = g.degree().unwrap_or(0);
+        if split > 0 && split < degree {
+            let mut factors = equal_degree_linear(&g);
+            factors.extend(equal_degree_linear(&f.div_rem(&g).0));
+            return factors;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(seed: u8) -> Element {
+        gf128::from_block(&[seed; 16])
+    }
+
+    #[test]
+    fn division() {
+        let a = Poly::new(vec![element(1), element(2), element(3), element(4)]);
+        let b = Poly::new(vec![element(5), element(6)]);
+        let (q, r) = a.div_rem(&b);
+        assert_eq!(q.mul(&b).add(&r), a);
+        assert_eq!(r.degree(), Some(0));
+    }
+
+    #[test]
+    fn finds_roots() {
+        // c (X - r1)(X - r2)(X - r3)
+        let roots = vec![element(7), element(11), element(42)];
+        let mut f = Poly::constant(element(9));
+        for &r in roots.iter() {
+            f = f.mul(&Poly::new(vec![r, gf128::ONE]));
+        }
+        // repeated roots are reported once
+        f = f.mul(&Poly::new(vec![roots[0], gf128::ONE]));
+
+        let mut expected = roots.clone();
+        expected.sort();
+        assert_eq!(f.roots(), expected);
+        for r in expected {
+            assert_eq!(f.eval(r), gf128::ZERO);
+        }
+    }
+}
@@ -12,6 +12,7 @@
 use num::traits::{Zero, One};
 use openssl::bn::{BigNum, MsbOption};
 use num::bigint::{ToBigUint, ToBigInt, BigUint, BigInt};
+use error::{Error, Result};
 
 
 // extended Euclidean GCD algorithm
@@ -65,26 +66,53 @@ pub fn invmod(a: &BigUint, n: &BigUint) -> Option<BigUint> {
 	Some(t.to_biguint().unwrap())
 }
 
- 
-pub fn prime_gen() -> BigUint {
-   let mut big = BigNum::new().unwrap();
+
+pub fn prime_gen() -> Result<BigUint> {
+   let mut big = BigNum::new()?;
 
    // Generates a 128-bit odd random number
-   big.rand(128, MsbOption::MAYBE_ZERO, true).unwrap();
-   BigUint::from_bytes_le(&big.to_vec())
+   big.rand(128, MsbOption::MAYBE_ZERO, true)?;
+   Ok(BigUint::from_bytes_le(&big.to_vec()))
 }
 
-pub fn rsa_keygen() -> (Vec<u8>, Vec<u8>){
-    let (p, q) = (prime_gen(), prime_gen());
-    let n = &p * &q;
+pub fn rsa_keygen() -> Result<(Vec<u8>, Vec<u8>)> {
+    // e = 3 has no inverse whenever 3 divides the totient, just pick new numbers
+    loop {
+        match rsa_keygen_from(&prime_gen()?, &prime_gen()?) {
+            Err(Error::NoInverse) => continue,
+            keys => return keys,
+        }
+    }
+}
+
+pub fn rsa_keygen_from(p: &BigUint, q: &BigUint) -> Result<(Vec<u8>, Vec<u8>)> {
+    let n = p * q;
 
     let one: &BigUint = &One::one();
     let et = (p - one) * (q - one);
     let e = 3.to_biguint().unwrap();
-    let d = invmod(&e, &et).unwrap();
+    let d = invmod(&e, &et).ok_or(Error::NoInverse)?;
 
     // public key is [e, n]
     // private key is [d, n]
     let (e_vec, n_vec, d_vec) = (e.to_bytes_le(), n.to_bytes_le(), d.to_bytes_le());
-    ([&e_vec[..], &n_vec[..]].concat().to_vec(), [&d_vec[..], &n_vec[..]].concat().to_vec())
+    Ok(([&e_vec[..], &n_vec[..]].concat().to_vec(), [&d_vec[..], &n_vec[..]].concat().to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keygen_without_inverse() {
+        // (7 - 1) * (13 - 1) is a multiple of 3
+        let (p, q) = (7.to_biguint().unwrap(), 13.to_biguint().unwrap());
+        match rsa_keygen_from(&p, &q) {
+            Err(Error::NoInverse) => (),
+            other => panic!("expected NoInverse, got {:?}", other),
+        }
+
+        let (p, q) = (5.to_biguint().unwrap(), 11.to_biguint().unwrap());
+        assert!(rsa_keygen_from(&p, &q).is_ok());
+    }
 }
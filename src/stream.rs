@@ -0,0 +1,298 @@
+// std::io adapters over the keyed CBC and CTR contexts, so inputs never have to be held
+// in memory as a whole. Partial blocks are buffered until the next write or `finish`.
+
+use std::io::{self, Read, Write};
+use conversions::{pad_pkcs7, unpad_pkcs7};
+use crypter::{BlockCipher, Cbc, Ctr, CounterBlock};
+use error::{Error, Result};
+
+
+fn invalid_data(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// the CBC chain or the CTR counter has already moved past bytes the inner writer may not
+// have taken, so after a failed write the stream can't be resumed
+fn poisoned() -> io::Error {
+    io::Error::other("an earlier write to the inner writer failed")
+}
+
+/// Encrypts everything written to it with CBC, PKCS#7 padding is applied by `finish`
+pub struct CbcEncryptor<C: BlockCipher, W: Write> {
+    context: Cbc<C>,
+    inner: W,
+    buffer: Vec<u8>,
+    poisoned: bool,
+}
+
+impl<C: BlockCipher, W: Write> CbcEncryptor<C, W> {
+    pub fn new(cipher: C, iv: &[u8], inner: W) -> Result<CbcEncryptor<C, W>> {
+        Ok(CbcEncryptor { context: Cbc::new(cipher, iv)?, inner, buffer: vec!(), poisoned: false })
+    }
+
+    /// pads and writes out the final block, then hands back the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        // a block aligned stream ends on a whole block of padding
+        let block_size = self.context.cipher().block_size();
+        pad_pkcs7(&mut self.buffer, block_size).map_err(|e| invalid_data(Error::from(e)))?;
+
+        self.context.encrypt_blocks(&mut self.buffer).map_err(invalid_data)?;
+        self.inner.write_all(&self.buffer)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<C: BlockCipher, W: Write> Write for CbcEncryptor<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        let block_size = self.context.cipher().block_size();
+        self.buffer.extend_from_slice(buf);
+
+        let ready = self.buffer.len() - self.buffer.len() % block_size;
+        if ready > 0 {
+            self.context.encrypt_blocks(&mut self.buffer[..ready]).map_err(invalid_data)?;
+            if let Err(e) = self.inner.write_all(&self.buffer[..ready]) {
+                self.poisoned = true;
+                return Err(e);
+            }
+            self.buffer.drain(..ready);
+        }
+        Ok(buf.len())
+    }
+
+    // a partial block can't be flushed until finish is called
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts CBC cipher text written to it. The last block is always held back since it
+/// carries the padding, which is stripped by `finish`.
+pub struct CbcDecryptor<C: BlockCipher, W: Write> {
+    context: Cbc<C>,
+    inner: W,
+    buffer: Vec<u8>,
+    poisoned: bool,
+}
+
+impl<C: BlockCipher, W: Write> CbcDecryptor<C, W> {
+    pub fn new(cipher: C, iv: &[u8], inner: W) -> Result<CbcDecryptor<C, W>> {
+        Ok(CbcDecryptor { context: Cbc::new(cipher, iv)?, inner, buffer: vec!(), poisoned: false })
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        let block_size = self.context.cipher().block_size();
+        if self.buffer.is_empty() || !self.buffer.len().is_multiple_of(block_size) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "cipher text is not block aligned"));
+        }
+
+        self.context.decrypt_blocks(&mut self.buffer).map_err(invalid_data)?;
+        unpad_pkcs7(&mut self.buffer, block_size).map_err(|e| invalid_data(Error::from(e)))?;
+        self.inner.write_all(&self.buffer)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<C: BlockCipher, W: Write> Write for CbcDecryptor<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        let block_size = self.context.cipher().block_size();
+        self.buffer.extend_from_slice(buf);
+
+        // keep at least one full block around for finish
+        let whole = self.buffer.len() - self.buffer.len() % block_size;
+        let ready = if whole == self.buffer.len() { whole.saturating_sub(block_size) } else { whole };
+        if ready > 0 {
+            self.context.decrypt_blocks(&mut self.buffer[..ready]).map_err(invalid_data)?;
+            if let Err(e) = self.inner.write_all(&self.buffer[..ready]) {
+                self.poisoned = true;
+                return Err(e);
+            }
+            self.buffer.drain(..ready);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// CTR over a stream. Reading pulls bytes from an inner reader and writing pushes bytes to
+/// an inner writer, either way they come out xored with the keystream.
+pub struct CtrStream<C: BlockCipher, S> {
+    context: Ctr<C>,
+    inner: S,
+    // what goes out on a write, kept around between writes
+    scratch: Vec<u8>,
+    poisoned: bool,
+}
+
+impl<C: BlockCipher, S> CtrStream<C, S> {
+    pub fn new(cipher: C, nonce: u64, inner: S) -> Result<CtrStream<C, S>> {
+        Ok(CtrStream { context: Ctr::new(cipher, nonce)?, inner, scratch: vec!(), poisoned: false })
+    }
+
+    pub fn with_counter_block(cipher: C, counter_block: CounterBlock, inner: S) -> Result<CtrStream<C, S>> {
+        Ok(CtrStream { context: Ctr::with_counter_block(cipher, counter_block)?, inner, scratch: vec!(), poisoned: false })
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<C: BlockCipher, R: Read> Read for CtrStream<C, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.context.apply_key_stream(&mut buf[..count]);
+        Ok(count)
+    }
+}
+
+impl<C: BlockCipher, W: Write> Write for CtrStream<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        self.scratch.clear();
+        self.scratch.extend_from_slice(buf);
+        self.context.apply_key_stream(&mut self.scratch);
+        if let Err(e) = self.inner.write_all(&self.scratch) {
+            self.poisoned = true;
+            return Err(e);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::Aes;
+    use crypter::{cbc, ctr, random_bytes};
+    use openssl::symm::Mode;
+
+    #[test]
+    fn cbc_round_trip_in_pieces() {
+        let (key, iv) = (b"YELLOW SUBMARINE", [9u8; 16]);
+        let plain = random_bytes(1000, 1000);
+
+        let mut encryptor = CbcEncryptor::new(Aes::new(key).unwrap(), &iv, vec!()).unwrap();
+        for chunk in plain.chunks(37) {
+            encryptor.write_all(chunk).unwrap();
+        }
+        let encrypted = encryptor.finish().unwrap();
+
+        let mut padded = plain.clone();
+        pad_pkcs7(&mut padded, 16).unwrap();
+        assert_eq!(encrypted, cbc(&Aes::new(key).unwrap(), &iv, &padded, Mode::Encrypt).unwrap());
+
+        let mut decryptor = CbcDecryptor::new(Aes::new(key).unwrap(), &iv, vec!()).unwrap();
+        for chunk in encrypted.chunks(16) {
+            decryptor.write_all(chunk).unwrap();
+        }
+        assert_eq!(decryptor.finish().unwrap(), plain);
+    }
+
+    #[test]
+    fn cbc_block_aligned_and_empty() {
+        let (key, iv) = (b"YELLOW SUBMARINE", [3u8; 16]);
+        for len in &[0, 16, 64] {
+            let plain = random_bytes(*len, *len);
+            let mut encryptor = CbcEncryptor::new(Aes::new(key).unwrap(), &iv, vec!()).unwrap();
+            encryptor.write_all(&plain).unwrap();
+            let encrypted = encryptor.finish().unwrap();
+            // a whole block of padding on the end
+            assert_eq!(encrypted.len(), len + 16);
+
+            let mut decryptor = CbcDecryptor::new(Aes::new(key).unwrap(), &iv, vec!()).unwrap();
+            decryptor.write_all(&encrypted).unwrap();
+            assert_eq!(decryptor.finish().unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn cbc_decryptor_rejects_truncated_input() {
+        let mut decryptor = CbcDecryptor::new(Aes::new(b"YELLOW SUBMARINE").unwrap(), &[0; 16], vec!()).unwrap();
+        decryptor.write_all(&[0; 20]).unwrap();
+        assert!(decryptor.finish().is_err());
+
+        let decryptor = CbcDecryptor::new(Aes::new(b"YELLOW SUBMARINE").unwrap(), &[0; 16], vec!()).unwrap();
+        assert!(decryptor.finish().is_err());
+
+        assert!(CbcEncryptor::new(Aes::new(b"YELLOW SUBMARINE").unwrap(), &[0; 8], vec!()).is_err());
+    }
+
+    // takes `room` bytes and fails every write after that
+    struct ShortWriter {
+        room: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.room == 0 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+            let count = buf.len().min(self.room);
+            self.room -= count;
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn poisoned_after_failed_write() {
+        let key = b"YELLOW SUBMARINE";
+        let mut encryptor = CbcEncryptor::new(Aes::new(key).unwrap(), &[0; 16], ShortWriter { room: 16 }).unwrap();
+        encryptor.write_all(&[1; 16]).unwrap();
+        assert!(encryptor.write_all(&[2; 16]).is_err());
+        assert!(encryptor.write_all(&[3; 16]).is_err());
+        assert!(encryptor.finish().is_err());
+
+        let mut decryptor = CbcDecryptor::new(Aes::new(key).unwrap(), &[0; 16], ShortWriter { room: 0 }).unwrap();
+        assert!(decryptor.write_all(&[1; 32]).is_err());
+        assert!(decryptor.write_all(&[2; 16]).is_err());
+        assert!(decryptor.finish().is_err());
+
+        let mut writer = CtrStream::new(Aes::new(key).unwrap(), 0, ShortWriter { room: 10 }).unwrap();
+        writer.write_all(&[1; 10]).unwrap();
+        assert!(writer.write_all(&[2; 10]).is_err());
+        assert!(writer.write_all(&[3; 10]).is_err());
+    }
+
+    #[test]
+    fn ctr_read_and_write() {
+        let key = b"YELLOW SUBMARINE";
+        let plain = random_bytes(333, 333);
+
+        let mut encrypted = vec!();
+        CtrStream::new(Aes::new(key).unwrap(), 7, &plain[..]).unwrap().read_to_end(&mut encrypted).unwrap();
+        assert_eq!(encrypted, ctr(&Aes::new(key).unwrap(), 7, &plain).unwrap());
+
+        let mut writer = CtrStream::new(Aes::new(key).unwrap(), 7, vec!()).unwrap();
+        for chunk in encrypted.chunks(10) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.into_inner(), plain);
+    }
+}
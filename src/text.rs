@@ -1,6 +1,7 @@
 // this module will generate character frequency for texts
 
 use std::collections::HashMap;
+use error::{Error, Result};
 
 #[derive(Debug)]
 pub struct CharFreq {
@@ -84,6 +85,15 @@ impl CharFreq {
         diff
     }
 
+    /// how often c turns up, case insensitive
+    pub fn probability(&self, c: char) -> f32 {
+        let c = c.to_lowercase().next().unwrap();
+        match self.total {
+            0 => 0.0,
+            total => *self.counts.get(&c).unwrap_or(&0) as f32 / total as f32,
+        }
+    }
+
     pub fn dist_from_string(&self, s: &str) -> f32 {
         let mut other = CharFreq::new();
         other.count_all(s);
@@ -91,14 +101,17 @@ impl CharFreq {
     }
 }
 
-pub fn kv_parse(input: String) -> HashMap<String, String>{
+pub fn kv_parse(input: String) -> Result<HashMap<String, String>> {
     let mut retval: HashMap<String, String> = HashMap::new();
     for item in input.split('&') {
         let pair: Vec<&str> = item.split('=').collect();
         let mut str_pair = pair.iter().map(|s| String::from(*s));
-        retval.insert(str_pair.next().unwrap(), str_pair.next().unwrap());
+        match (str_pair.next(), str_pair.next()) {
+            (Some(k), Some(v)) => retval.insert(k, v),
+            _ => return Err(Error::MalformedKeyValue(item.to_string())),
+        };
     }
-    retval
+    Ok(retval)
 }
 
 pub fn kv_encode(map: HashMap<String, String>) -> String {
@@ -111,17 +124,28 @@ pub fn kv_encode(map: HashMap<String, String>) -> String {
     retval.join("&")
 }
 
+// encoded in a fixed order, the way a real token format would be
 pub fn profile_for(email: &str, role: &str) -> String {
-    let sanitized_email = email.replace('@', "").replace('=',"");
-    let obj: HashMap<String, String> = [
-        ("email".to_string(), sanitized_email),
-        ("uid".to_string(), "10".to_string()),
-        ("role".to_string(), role.replace('@', "").replace('=',""))
-    ].iter().cloned().collect();
-
-    kv_encode(obj)
+    let strip = |s: &str| s.replace(['@', '=', '&'], "");
+    format!("email={}&uid=10&role={}", strip(email), strip(role))
 }
 
 pub fn sanitize_for_url(raw_str: &str) -> String {
     raw_str.replace("=", "%3D").replace(";", "%3B")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_parse_without_value() {
+        let parsed = kv_parse("foo=bar&baz=qux".to_string()).unwrap();
+        assert_eq!(parsed["baz"], "qux");
+
+        match kv_parse("foo=bar&baz".to_string()) {
+            Err(Error::MalformedKeyValue(ref item)) if item == "baz" => (),
+            other => panic!("expected MalformedKeyValue, got {:?}", other),
+        }
+    }
+}